@@ -10,8 +10,8 @@ use embassy_rp::usb::{Driver, InterruptHandler};
 use embassy_rp::{bind_interrupts, spi};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{with_timeout, Delay, Duration, Timer};
-use rfm69_async::mac::{receive_packet, send_packet};
+use embassy_time::{with_timeout, Delay, Duration, Instant, Timer};
+use rfm69_async::mac::{receive_packet, send_packet, EmbassyTimer, MacConfig, MacState};
 use rfm69_async::{config, Address, Flags, Rfm69};
 use {defmt_rtt as _, panic_probe as _};
 
@@ -53,7 +53,7 @@ async fn main(spawner: Spawner) {
 
     let rfm_spi = SpiDevice::new(&spi_bus, cs);
 
-    let rfm = config::my_defaults(Rfm69::new(rfm_spi, reset, dio0, Delay), 42, 868_480_000).await;
+    let rfm = config::my_defaults(Rfm69::new(rfm_spi, reset, dio0, Delay), 42, 868_480_000, None).await;
     let mut rfm = match rfm {
         Ok(r) => r,
         Err(e) => {
@@ -70,17 +70,35 @@ async fn main(spawner: Spawner) {
 
     let mut counter = 0;
     let own_address = Address::Unicast(42);
+    let mut timer = EmbassyTimer;
+    let mac_config = MacConfig::for_radio(&rfm);
+    // Start with a different sequence number after each restart
+    let mut mac_state = MacState::new(Instant::now().as_micros() as u8);
 
     log::info!("Own address: {:?}", own_address);
     loop {
         let to_address = Address::Unicast(70);
         log::info!("Sending packet to {:?}", to_address);
-        let res = send_packet(&mut rfm, own_address, to_address, Flags::Ack(3), &[0xAA, counter as u8]).await;
+        let res = send_packet(
+            &mut rfm,
+            &mut timer,
+            &mac_config,
+            &mut mac_state,
+            own_address,
+            to_address,
+            Flags::new().with_ack_request(true),
+            &[0xAA, counter as u8],
+        )
+        .await;
         log::info!("Tx Res {:?}", res);
 
         counter += 1;
         log::info!("Trying to receive packet for 10 seconds (# {})", counter);
-        let rx_result = with_timeout(Duration::from_secs(10), receive_packet(&mut rfm, own_address)).await;
+        let rx_result = with_timeout(
+            Duration::from_secs(10),
+            receive_packet(&mut rfm, &mut timer, &mac_config, &mut mac_state, own_address),
+        )
+        .await;
         match rx_result {
             Ok(Ok(packet)) => {
                 log::info!("Rx Packet {:?}", packet);
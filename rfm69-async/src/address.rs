@@ -2,20 +2,29 @@
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Address {
     Broadcast,
-    Unicast(u8),
+    /// Node address, the frame format limits the range (see `FrameFormat`)
+    Unicast(u16),
 }
 impl Address {
     pub(crate) fn from_u8(addr: u8) -> Address {
         if addr == 255 {
             Self::Broadcast
         } else {
-            Self::Unicast(addr)
+            Self::Unicast(addr.into())
         }
     }
 
-    pub(crate) fn as_u8(&self) -> u8 {
+    /// Returns the address byte, `None` if the address does not fit into it
+    pub(crate) fn as_u8(&self) -> Option<u8> {
         match self {
-            Self::Broadcast => 255,
+            Self::Broadcast => Some(255),
+            Self::Unicast(addr) => u8::try_from(*addr).ok().filter(|addr| *addr != 255),
+        }
+    }
+
+    pub(crate) fn as_u16(&self) -> u16 {
+        match self {
+            Self::Broadcast => u16::MAX,
             Self::Unicast(addr) => *addr,
         }
     }
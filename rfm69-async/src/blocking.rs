@@ -0,0 +1,422 @@
+//! Blocking front end of the driver
+//!
+//! For applications without an async executor, e.g. bare metal super loops or RTIC. The blocking hal
+//! implementations are adapted to the async traits and each call runs the async driver to completion,
+//! so both front ends share the register logic, the packet codec and the configurations:
+//!
+//! ```ignore
+//! let rfm = blocking::Rfm69::new(spi, reset, Some(dio0), delay);
+//! let mut rfm = blocking::my_defaults(rfm, 42, 868_000_000, None)?;
+//! rfm.send(&packet)?;
+//! ```
+//!
+//! Waiting for a dio pin is done by polling it in a busy loop, there is no interrupt to sleep on.
+//! Without a receive timeout (`Timeouts::rx_us` is `None` by default), `recv` keeps the cpu busy
+//! until a packet arrives. If that costs too much power, receive with a timeout and sleep in
+//! between, or use the async driver with an interrupt capable pin.
+
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use embedded_hal_1::delay::DelayUs;
+use embedded_hal_1::digital::{self, InputPin, OutputPin};
+use embedded_hal_1::spi::{self, Operation, SpiDevice};
+use heapless::Vec;
+
+use crate::config::{RadioHeadModem, Rfm69Config};
+use crate::error::{Error, RecoveryCause};
+use crate::frame::{FrameCodec, FrameFormat};
+use crate::registers::*;
+use crate::rfm::yield_now;
+
+/// Longest blocking delay, before the dio pins are checked again during a timeout
+const DELAY_STEP_US: u32 = 50;
+
+/// The rfm69 transceiver with blocking hal implementations
+///
+/// See `crate::Rfm69` for the documentation of the functions.
+pub struct Rfm69<SPI, RESET, DIO0, DELAY, CODEC = FrameFormat>
+where
+    SPI: SpiDevice<u8>,
+    RESET: OutputPin,
+    DIO0: InputPin,
+    DELAY: DelayUs,
+{
+    inner: crate::Rfm69<BlockingSpi<SPI>, RESET, BlockingPin<DIO0>, BlockingDelay<DELAY>, CODEC>,
+}
+
+/// Generates blocking functions that run the async function with the same name
+macro_rules! blocking {
+    ($($name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            pub fn $name(&mut self, $($arg: $ty),*) -> Result<$ret, Error<E, RESET::Error, DIO0::Error>> {
+                block_on(self.inner.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+impl<SPI, RESET, DIO0, DELAY, E> Rfm69<SPI, RESET, DIO0, DELAY>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin,
+    DELAY: DelayUs,
+{
+    /// Returns a Rfm69 instance
+    ///
+    /// See `crate::Rfm69::new`.
+    pub fn new(spi: SPI, reset: RESET, dio0: Option<DIO0>, delay: DELAY) -> Self {
+        Self {
+            inner: crate::Rfm69::new(BlockingSpi(spi), reset, dio0.map(BlockingPin), BlockingDelay(delay)),
+        }
+    }
+}
+
+impl<SPI, RESET, DIO0, DELAY, E, CODEC> Rfm69<SPI, RESET, DIO0, DELAY, CODEC>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin,
+    DELAY: DelayUs,
+    CODEC: FrameCodec,
+{
+    pub fn with_crystal_offset(self, offset: CrystalOffset) -> Self {
+        Self {
+            inner: self.inner.with_crystal_offset(offset),
+        }
+    }
+
+    pub fn with_variant(self, variant: Variant) -> Self {
+        Self {
+            inner: self.inner.with_variant(variant),
+        }
+    }
+
+    pub fn with_timeouts(self, timeouts: Timeouts) -> Self {
+        Self {
+            inner: self.inner.with_timeouts(timeouts),
+        }
+    }
+
+    pub fn with_recovery_after_timeouts(self, timeouts: u8) -> Self {
+        Self {
+            inner: self.inner.with_recovery_after_timeouts(timeouts),
+        }
+    }
+
+    pub fn with_dio1(self, dio1: DIO0) -> Self {
+        Self {
+            inner: self.inner.with_dio1(BlockingPin(dio1)),
+        }
+    }
+
+    pub fn with_codec<C: FrameCodec>(self, codec: C) -> Rfm69<SPI, RESET, DIO0, DELAY, C> {
+        Rfm69 {
+            inner: self.inner.with_codec(codec),
+        }
+    }
+
+    pub fn codec(&self) -> &CODEC {
+        self.inner.codec()
+    }
+
+    /// Current cached active mode
+    pub fn mode(&self) -> OpMode {
+        self.inner.mode
+    }
+
+    pub fn recoveries(&self) -> u32 {
+        self.inner.recoveries()
+    }
+
+    blocking! {
+        reset() -> ();
+        set_mode(mode: OpMode) -> ();
+        modulation(modulation: Modulation) -> ();
+        bit_rate(bit_rate: u32) -> u32;
+        frequency(frequency: u32) -> u32;
+        fdev(fdev: u32) -> u32;
+        fei() -> i32;
+        preamble_length(length: u16) -> ();
+        sync(sync: &[u8]) -> ();
+        packet(packet_config: PacketConfig) -> ();
+        node_address(address: u8) -> ();
+        broadcast_address(address: u8) -> ();
+        aes(key: Option<&[u8; 16]>) -> ();
+        output_power(power: OutputPower) -> ();
+        tx_power(dbm: i8) -> ();
+        fifo_mode(mode: FifoMode) -> ();
+        lna(lna: LnaConfig) -> ();
+        rssi_threshold(threshold: u8) -> ();
+        continuous_dagc(cdagc: ContinuousDagc) -> ();
+        read_modulation() -> Modulation;
+        read_bit_rate() -> u32;
+        read_frequency() -> u32;
+        read_fdev() -> u32;
+        read_preamble_length() -> u16;
+        read_sync() -> Vec<u8, 8>;
+        read_packet_config() -> PacketConfig;
+        read_node_address() -> u8;
+        read_broadcast_address() -> u8;
+        read_aes() -> Option<[u8; 16]>;
+        read_output_power() -> OutputPower;
+        read_fifo_mode() -> FifoMode;
+        read_lna() -> LnaConfig;
+        read_rssi_threshold() -> u8;
+        read_continuous_dagc() -> ContinuousDagc;
+        supervise() -> Option<RecoveryCause>;
+        recover(cause: RecoveryCause) -> ();
+        is_mode_ready() -> bool;
+        is_packet_sent() -> bool;
+        is_packet_ready() -> bool;
+        read_all_regs() -> [u8; 0x4f];
+        send(frame: &CODEC::Frame) -> ();
+        recv() -> CODEC::Frame;
+        listen(config: ListenConfig) -> ();
+        start_listen() -> ();
+        stop_listen() -> ();
+        listen_recv() -> CODEC::Frame;
+        abort() -> ();
+    }
+
+    pub fn rx_bw<RxBwT>(&mut self, rx_bw: RxBw<RxBwT>) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq,
+    {
+        block_on(self.inner.rx_bw(rx_bw))
+    }
+
+    pub fn afc<RxBwT>(&mut self, config: AfcConfig<RxBwT>) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq,
+    {
+        block_on(self.inner.afc(config))
+    }
+
+    pub fn apply<RxBwT>(&mut self, config: &Rfm69Config<RxBwT>) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq + Copy,
+    {
+        block_on(self.inner.apply(config))
+    }
+
+    pub fn read_rx_bw<RxBwT>(&mut self) -> Result<RxBw<RxBwT>, Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq,
+    {
+        block_on(self.inner.read_rx_bw())
+    }
+
+    pub fn read_config<RxBwT>(&mut self) -> Result<Rfm69Config<RxBwT>, Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq,
+    {
+        block_on(self.inner.read_config())
+    }
+
+    pub fn is_configured<RxBwT>(
+        &mut self,
+        config: &Rfm69Config<RxBwT>,
+    ) -> Result<bool, Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq + Clone + PartialEq,
+    {
+        block_on(self.inner.is_configured(config))
+    }
+}
+
+/// Configuration compatible with Low Power Lab radio protocol
+///
+/// See `crate::config::low_power_lab_defaults`.
+#[allow(clippy::type_complexity)]
+pub fn low_power_lab_defaults<SPI, RESET, DIO0, DELAY, CODEC, E>(
+    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    network_id: u8,
+    frequency: u32,
+    aes_key: Option<&[u8; 16]>,
+) -> Result<Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin,
+    DELAY: DelayUs,
+    CODEC: FrameCodec,
+{
+    rfm.inner = block_on(crate::config::low_power_lab_defaults(
+        rfm.inner, network_id, frequency, aes_key,
+    ))?;
+    Ok(rfm)
+}
+
+/// Configuration compatible with the RadioHead `RH_RF69` driver
+///
+/// See `crate::config::radio_head_defaults`.
+#[allow(clippy::type_complexity)]
+pub fn radio_head_defaults<SPI, RESET, DIO0, DELAY, CODEC, E>(
+    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    modem: RadioHeadModem,
+    frequency: u32,
+    aes_key: Option<&[u8; 16]>,
+) -> Result<Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin,
+    DELAY: DelayUs,
+    CODEC: FrameCodec,
+{
+    rfm.inner = block_on(crate::config::radio_head_defaults(rfm.inner, modem, frequency, aes_key))?;
+    Ok(rfm)
+}
+
+/// Custom configuration (gfsk, 100kBit/sec)
+///
+/// See `crate::config::my_defaults`.
+#[allow(clippy::type_complexity)]
+pub fn my_defaults<SPI, RESET, DIO0, DELAY, CODEC, E>(
+    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    network_id: u8,
+    frequency: u32,
+    aes_key: Option<&[u8; 16]>,
+) -> Result<Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin,
+    DELAY: DelayUs,
+    CODEC: FrameCodec,
+{
+    rfm.inner = block_on(crate::config::my_defaults(rfm.inner, network_id, frequency, aes_key))?;
+    Ok(rfm)
+}
+
+/// Blocking spi device used by the async driver
+pub struct BlockingSpi<SPI>(SPI);
+
+impl<SPI: SpiDevice<u8>> spi::ErrorType for BlockingSpi<SPI> {
+    type Error = SPI::Error;
+}
+
+impl<SPI: SpiDevice<u8>> embedded_hal_async::spi::SpiDeviceRead<u8> for BlockingSpi<SPI> {
+    async fn read_transaction(&mut self, operations: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        spi::SpiDeviceRead::read_transaction(&mut self.0, operations)
+    }
+}
+
+impl<SPI: SpiDevice<u8>> embedded_hal_async::spi::SpiDeviceWrite<u8> for BlockingSpi<SPI> {
+    async fn write_transaction(&mut self, operations: &[&[u8]]) -> Result<(), Self::Error> {
+        spi::SpiDeviceWrite::write_transaction(&mut self.0, operations)
+    }
+}
+
+impl<SPI: SpiDevice<u8>> embedded_hal_async::spi::SpiDevice<u8> for BlockingSpi<SPI> {
+    async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.0.transaction(operations)
+    }
+}
+
+/// Blocking input pin used by the async driver
+///
+/// Waiting reads the pin on every poll and never sleeps, see the module documentation.
+pub struct BlockingPin<PIN>(PIN);
+
+impl<PIN: InputPin> digital::ErrorType for BlockingPin<PIN> {
+    type Error = PIN::Error;
+}
+
+impl<PIN: InputPin> InputPin for BlockingPin<PIN> {
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.0.is_high()
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self.0.is_low()
+    }
+}
+
+impl<PIN: InputPin> BlockingPin<PIN> {
+    async fn wait_for_level(&mut self, high: bool) -> Result<(), PIN::Error> {
+        poll_fn(|cx| match self.0.is_high() {
+            Ok(level) if level == high => Poll::Ready(Ok(())),
+            Ok(_) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        })
+        .await
+    }
+}
+
+impl<PIN: InputPin> embedded_hal_async::digital::Wait for BlockingPin<PIN> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await?;
+        self.wait_for_level(true).await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await?;
+        self.wait_for_level(false).await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        let level = self.0.is_high()?;
+        self.wait_for_level(!level).await
+    }
+}
+
+/// Blocking delay used by the async driver
+///
+/// Long delays are split into steps, so a dio pin that is waited for concurrently is checked in
+/// between.
+pub struct BlockingDelay<DELAY>(DELAY);
+
+impl<DELAY: DelayUs> embedded_hal_async::delay::DelayUs for BlockingDelay<DELAY> {
+    async fn delay_us(&mut self, us: u32) {
+        let mut remaining = us;
+        while remaining > 0 {
+            let step = remaining.min(DELAY_STEP_US);
+            self.0.delay_us(step);
+            remaining -= step;
+            if remaining > 0 {
+                yield_now().await;
+            }
+        }
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.delay_us(1000).await;
+        }
+    }
+}
+
+/// Polls the future until it completes
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+/// Waker that does nothing, the future is polled continuously anyway
+fn noop_waker() -> Waker {
+    const VTABLE: RawWakerVTable =
+        RawWakerVTable::new(|_| RawWaker::new(core::ptr::null(), &VTABLE), |_| {}, |_| {}, |_| {});
+    // SAFETY: the vtable functions do not access the data pointer
+    unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+}
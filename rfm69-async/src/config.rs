@@ -4,118 +4,507 @@ use embedded_hal_1::digital::{InputPin, OutputPin};
 use embedded_hal_async::delay::DelayUs;
 use embedded_hal_async::digital::Wait;
 use embedded_hal_async::spi::SpiDevice;
+use heapless::Vec;
 
 use crate::error::Error;
+use crate::frame::FrameCodec;
 use crate::registers::*;
-use crate::rfm::Rfm69;
+use crate::rfm::{Rfm69, MAX_AES_PACKET_LENGTH};
+
+/// Reasons why a configuration is rejected by `Rfm69Config::validate`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// Bitrate is out of range for the modulation type
+    BitRate,
+    /// Frequency deviation is out of range
+    Fdev,
+    /// Frequency deviation plus half the bitrate exceeds 500 kHz
+    FdevBitRate,
+    /// Modulation index (2 * fdev / bitrate) is not between 0.5 and 10
+    ModulationIndex,
+    /// Rx bandwidth is too narrow for the signal, or does not belong to the modulation type
+    RxBw,
+    /// Frequency is outside of the supported bands
+    Frequency,
+    /// Sync words must not contain 0x00
+    SyncValue,
+    /// More than 8 sync words
+    SyncLength,
+    /// Fixed packet length is too large for aes
+    PacketSize,
+}
+
+/// Modem configurations of RadioHead's `RH_RF69::ModemConfigChoice`
+///
+/// The names follow RadioHead: `Rb` is the bitrate and `Fd` the frequency deviation in kHz. Only the
+/// fsk and gfsk choices are available, the ook ones are not supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RadioHeadModem {
+    FskRb2Fd5,
+    FskRb2_4Fd4_8,
+    FskRb4_8Fd9_6,
+    FskRb9_6Fd19_2,
+    FskRb19_2Fd38_4,
+    FskRb38_4Fd76_8,
+    FskRb57_6Fd120,
+    FskRb125Fd125,
+    FskRb250Fd250,
+    FskRb55555Fd50,
+    GfskRb2Fd5,
+    GfskRb2_4Fd4_8,
+    GfskRb4_8Fd9_6,
+    GfskRb9_6Fd19_2,
+    GfskRb19_2Fd38_4,
+    GfskRb38_4Fd76_8,
+    GfskRb57_6Fd120,
+    GfskRb125Fd125,
+    GfskRb250Fd250,
+    GfskRb55555Fd50,
+}
+
+impl RadioHeadModem {
+    fn is_gfsk(&self) -> bool {
+        matches!(
+            self,
+            Self::GfskRb2Fd5
+                | Self::GfskRb2_4Fd4_8
+                | Self::GfskRb4_8Fd9_6
+                | Self::GfskRb9_6Fd19_2
+                | Self::GfskRb19_2Fd38_4
+                | Self::GfskRb38_4Fd76_8
+                | Self::GfskRb57_6Fd120
+                | Self::GfskRb125Fd125
+                | Self::GfskRb250Fd250
+                | Self::GfskRb55555Fd50
+        )
+    }
+
+    /// Returns bitrate, frequency deviation and rx bandwidth
+    fn parameters(&self) -> (u32, u32, RxBw<RxBwFsk>) {
+        let rx_bw = |dcc_cutoff, rx_bw| RxBw { dcc_cutoff, rx_bw };
+        match self {
+            Self::FskRb2Fd5 | Self::GfskRb2Fd5 => (2_000, 5_000, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz20dot8)),
+            Self::FskRb2_4Fd4_8 | Self::GfskRb2_4Fd4_8 => {
+                (2_400, 4_800, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz20dot8))
+            }
+            Self::FskRb4_8Fd9_6 | Self::GfskRb4_8Fd9_6 => {
+                (4_800, 9_600, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz20dot8))
+            }
+            Self::FskRb9_6Fd19_2 | Self::GfskRb9_6Fd19_2 => {
+                (9_600, 19_200, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz25dot0))
+            }
+            Self::FskRb19_2Fd38_4 | Self::GfskRb19_2Fd38_4 => {
+                (19_200, 38_400, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz50dot0))
+            }
+            Self::FskRb38_4Fd76_8 | Self::GfskRb38_4Fd76_8 => {
+                (38_400, 76_800, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz100dot0))
+            }
+            Self::FskRb57_6Fd120 | Self::GfskRb57_6Fd120 => {
+                (57_600, 120_000, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz166dot7))
+            }
+            Self::FskRb125Fd125 | Self::GfskRb125Fd125 => {
+                (125_000, 125_000, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz250dot0))
+            }
+            Self::FskRb250Fd250 | Self::GfskRb250Fd250 => {
+                (250_000, 250_000, rx_bw(DccCutoff::Percent0dot125, RxBwFsk::Khz500dot0))
+            }
+            Self::FskRb55555Fd50 | Self::GfskRb55555Fd50 => {
+                (55_555, 50_000, rx_bw(DccCutoff::Percent4, RxBwFsk::Khz125dot0))
+            }
+        }
+    }
+}
+
+/// Complete radio configuration
+///
+/// The configuration is plain data. Start with one of the presets and change single parameters:
+///
+/// ```ignore
+/// let config = Rfm69Config::my_defaults(42, 868_000_000).bit_rate(50_000).fdev(40_000);
+/// rfm.apply(&config).await?;
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct Rfm69Config<T = RxBwFsk>
+where
+    T: RxBwFreq,
+{
+    pub modulation: Modulation,
+    pub bit_rate: u32,
+    pub fdev: u32,
+    pub rx_bw: RxBw<T>,
+    pub preamble_length: u16,
+    /// Sync words, empty to disable the sync word detection
+    pub sync: Vec<u8, 8>,
+    pub packet: PacketConfig,
+    /// Address of the node, if the packet filtering is enabled
+    pub node_address: u8,
+    /// Address of broadcasts, if the packet filtering accepts them
+    pub broadcast_address: u8,
+    pub fifo_mode: FifoMode,
+    pub lna: LnaConfig,
+    pub rssi_threshold: u8,
+    pub continuous_dagc: ContinuousDagc,
+    /// Output power in dBm, using the power amplifiers of the configured variant
+    pub tx_power: i8,
+    pub frequency: u32,
+    pub aes_key: Option<[u8; 16]>,
+}
+
+impl Rfm69Config<RxBwFsk> {
+    /// Configuration compatible with Low Power Lab radio protocol
+    ///
+    /// See `<https://github.com/LowPowerLab/RFM69>`
+    pub fn low_power_lab(network_id: u8, frequency: u32) -> Self {
+        Self {
+            modulation: Modulation {
+                data_mode: DataMode::Packet,
+                modulation_type: ModulationType::Fsk,
+                shaping: ModulationShaping::Shaping00,
+            },
+            bit_rate: 55_555,
+            fdev: 50_000,
+            rx_bw: RxBw {
+                dcc_cutoff: DccCutoff::Percent4,
+                rx_bw: RxBwFsk::Khz125dot0,
+            },
+            preamble_length: 3,
+            sync: Vec::from_slice(&[0x2d, network_id]).unwrap(),
+            packet: PacketConfig {
+                format: PacketFormat::Variable(66),
+                dc: PacketDc::None,
+                filtering: PacketFiltering::None,
+                crc: true,
+                interpacket_rx_delay: InterPacketRxDelay::Delay2Bits,
+                auto_rx_restart: true,
+            },
+            node_address: 0,
+            broadcast_address: 0, // RF69_BROADCAST_ADDR
+            fifo_mode: FifoMode::NotEmpty,
+            lna: LnaConfig {
+                zin: LnaImpedance::Ohm200,
+                gain_select: LnaGain::AgcLoop,
+            },
+            rssi_threshold: 220,
+            continuous_dagc: ContinuousDagc::ImprovedMarginAfcLowBetaOn0,
+            tx_power: 13,
+            frequency,
+            aes_key: None,
+        }
+    }
+
+    /// Configuration compatible with the RadioHead `RH_RF69` driver
+    ///
+    /// See `<https://www.airspayce.com/mikem/arduino/RadioHead/>`. These are the settings of
+    /// `RH_RF69::init` with the given modem configuration: 4 preamble bytes, sync words 0x2d 0xd4,
+    /// whitening, crc and +13 dBm. Bitrate and frequency deviation may differ from RadioHead's table
+    /// by one register step.
+    ///
+    /// RadioHead uses rx bandwidths, that are too narrow for some of its modem configurations
+    /// (e.g. 125 kHz for `FskRb57_6Fd120`). Those are widened to the narrowest bandwidth that passes
+    /// `validate`, which only affects the receiver.
+    pub fn radio_head(modem: RadioHeadModem, frequency: u32) -> Self {
+        let (bit_rate, fdev, rx_bw) = modem.parameters();
+        Self {
+            modulation: Modulation {
+                data_mode: DataMode::Packet,
+                modulation_type: ModulationType::Fsk,
+                shaping: if modem.is_gfsk() {
+                    ModulationShaping::Shaping01 // gfsk with bt = 1.0
+                } else {
+                    ModulationShaping::Shaping00
+                },
+            },
+            bit_rate,
+            fdev,
+            rx_bw,
+            preamble_length: 4,
+            sync: Vec::from_slice(&[0x2d, 0xd4]).unwrap(),
+            packet: PacketConfig {
+                format: PacketFormat::Variable(MAX_AES_PACKET_LENGTH as u8),
+                dc: PacketDc::Whitening,
+                filtering: PacketFiltering::None,
+                crc: true,
+                interpacket_rx_delay: InterPacketRxDelay::Delay1Bit,
+                auto_rx_restart: true,
+            },
+            node_address: 0,
+            broadcast_address: 0xff, // RH_BROADCAST_ADDRESS
+            fifo_mode: FifoMode::NotEmpty,
+            lna: LnaConfig {
+                zin: LnaImpedance::Ohm200,
+                gain_select: LnaGain::AgcLoop,
+            },
+            rssi_threshold: 228,
+            continuous_dagc: ContinuousDagc::ImprovedMarginAfcLowBetaOn0,
+            tx_power: 13,
+            frequency,
+            aes_key: None,
+        }
+    }
+
+    /// Custom configuration (gfsk, 100kBit/sec)
+    ///
+    /// This uses gfsk to reduce the used bandwidth and a 100kBit/sec data rate.
+    /// Otherwise it is similar to the Low Power Lab configuration.
+    pub fn my_defaults(network_id: u8, frequency: u32) -> Self {
+        let mut config = Self::low_power_lab(network_id, frequency);
+        config.modulation.shaping = ModulationShaping::Shaping10; // gfsk with bt = 0.5
+        config.bit_rate = 100_000;
+        config.packet.format = PacketFormat::Variable(255);
+        config.broadcast_address = 0xff; // broadcast of the native frame format
+        config.lna.zin = LnaImpedance::Ohm50;
+        config
+    }
+}
+
+impl<T> Rfm69Config<T>
+where
+    T: RxBwFreq,
+{
+    pub fn modulation(mut self, modulation: Modulation) -> Self {
+        self.modulation = modulation;
+        self
+    }
+
+    pub fn bit_rate(mut self, bit_rate: u32) -> Self {
+        self.bit_rate = bit_rate;
+        self
+    }
+
+    pub fn fdev(mut self, fdev: u32) -> Self {
+        self.fdev = fdev;
+        self
+    }
+
+    /// Sets the rx bandwidth, which may change the type of the bandwidth (fsk or ook)
+    pub fn rx_bw<U>(self, rx_bw: RxBw<U>) -> Rfm69Config<U>
+    where
+        U: RxBwFreq,
+    {
+        Rfm69Config {
+            modulation: self.modulation,
+            bit_rate: self.bit_rate,
+            fdev: self.fdev,
+            rx_bw,
+            preamble_length: self.preamble_length,
+            sync: self.sync,
+            packet: self.packet,
+            node_address: self.node_address,
+            broadcast_address: self.broadcast_address,
+            fifo_mode: self.fifo_mode,
+            lna: self.lna,
+            rssi_threshold: self.rssi_threshold,
+            continuous_dagc: self.continuous_dagc,
+            tx_power: self.tx_power,
+            frequency: self.frequency,
+            aes_key: self.aes_key,
+        }
+    }
+
+    pub fn preamble_length(mut self, length: u16) -> Self {
+        self.preamble_length = length;
+        self
+    }
+
+    /// Sets the sync words
+    ///
+    /// Maximal sync length is 8, pass empty buffer to disable the sync word detection. Returns
+    /// `ConfigError::SyncLength` for longer sync words.
+    pub fn sync(mut self, sync: &[u8]) -> Result<Self, ConfigError> {
+        self.sync = Vec::from_slice(sync).map_err(|_| ConfigError::SyncLength)?;
+        Ok(self)
+    }
+
+    pub fn packet(mut self, packet: PacketConfig) -> Self {
+        self.packet = packet;
+        self
+    }
+
+    /// Lets the radio drop frames for other nodes
+    ///
+    /// Frames are accepted, if their first byte (after the length byte) is the node address or the
+    /// broadcast address. The frame formats put the destination there, see `FrameFormat`.
+    pub fn address_filtering(mut self, node_address: u8) -> Self {
+        self.node_address = node_address;
+        self.packet.filtering = PacketFiltering::Broadcast;
+        self
+    }
+
+    pub fn broadcast_address(mut self, address: u8) -> Self {
+        self.broadcast_address = address;
+        self
+    }
+
+    pub fn fifo_mode(mut self, mode: FifoMode) -> Self {
+        self.fifo_mode = mode;
+        self
+    }
+
+    pub fn lna(mut self, lna: LnaConfig) -> Self {
+        self.lna = lna;
+        self
+    }
+
+    pub fn rssi_threshold(mut self, threshold: u8) -> Self {
+        self.rssi_threshold = threshold;
+        self
+    }
+
+    pub fn continuous_dagc(mut self, cdagc: ContinuousDagc) -> Self {
+        self.continuous_dagc = cdagc;
+        self
+    }
+
+    pub fn tx_power(mut self, dbm: i8) -> Self {
+        self.tx_power = dbm;
+        self
+    }
+
+    pub fn frequency(mut self, frequency: u32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    /// Sets the aes key, `None` disables encryption
+    pub fn aes_key(mut self, key: Option<&[u8; 16]>) -> Self {
+        self.aes_key = key.copied();
+        self
+    }
+
+    /// Checks the configuration against the limits of the datasheet
+    ///
+    /// The output power is checked when the configuration is applied, because its range depends on
+    /// the variant of the module.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let fsk = matches!(self.modulation.modulation_type, ModulationType::Fsk);
+        let max_bit_rate = if fsk { 300_000 } else { 32_768 };
+        if !(1_200..=max_bit_rate).contains(&self.bit_rate) {
+            return Err(ConfigError::BitRate);
+        }
+
+        // Single side bandwidth of the signal
+        let signal_bw = if fsk {
+            if !(600..=300_000).contains(&self.fdev) {
+                return Err(ConfigError::Fdev);
+            }
+            if self.fdev + self.bit_rate / 2 > 500_000 {
+                return Err(ConfigError::FdevBitRate);
+            }
+            let modulation_index = 2 * self.fdev * 10 / self.bit_rate;
+            if !(5..=100).contains(&modulation_index) {
+                return Err(ConfigError::ModulationIndex);
+            }
+            self.fdev + self.bit_rate / 2
+        } else {
+            self.bit_rate
+        };
+        // The bandwidth in Hz depends on the modulation type
+        if T::from_value(self.rx_bw.rx_bw.value(), self.modulation.modulation_type).is_none() {
+            return Err(ConfigError::RxBw);
+        }
+        if self.rx_bw.rx_bw.hz() < signal_bw {
+            return Err(ConfigError::RxBw);
+        }
+
+        let bands = [
+            290_000_000..=340_000_000,
+            424_000_000..=510_000_000,
+            862_000_000..=1_020_000_000,
+        ];
+        if !bands.iter().any(|band| band.contains(&self.frequency)) {
+            return Err(ConfigError::Frequency);
+        }
+
+        if self.sync.contains(&0x00) {
+            return Err(ConfigError::SyncValue);
+        }
+
+        // Variable length packets are checked when sending, the length is only an upper limit for rx
+        if let PacketFormat::Fixed(length) = self.packet.format {
+            if self.aes_key.is_some() && length as usize > MAX_AES_PACKET_LENGTH {
+                return Err(ConfigError::PacketSize);
+            }
+        }
+        Ok(())
+    }
+}
 
 /// Configuration compatible with Low Power Lab radio protocol
 ///
-/// See `<https://github.com/LowPowerLab/RFM69>`
+/// See `<https://github.com/LowPowerLab/RFM69>` and `Rfm69Config::low_power_lab`. To exchange packets
+/// with nodes using that library, the radio must use the codec `FrameFormat::LowPowerLab`.
 ///
-/// Note: This configuration is not tested/used.
-pub async fn low_power_lab_defaults<SPI, RESET, DIO0, DELAY, E>(
-    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY>,
+/// Payload encryption is enabled, if an aes key is given.
+pub async fn low_power_lab_defaults<SPI, RESET, DIO0, DELAY, CODEC, E>(
+    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
     network_id: u8,
     frequency: u32,
-) -> Result<Rfm69<SPI, RESET, DIO0, DELAY>, Error<E, RESET::Error, DIO0::Error>>
+    aes_key: Option<&[u8; 16]>,
+) -> Result<Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    CODEC: FrameCodec,
+{
+    rfm.reset().await?;
+    rfm.apply(&Rfm69Config::low_power_lab(network_id, frequency).aes_key(aes_key))
+        .await?;
+    rfm.set_mode(OpMode::Sleep).await?;
+    Ok(rfm)
+}
+
+/// Configuration compatible with the RadioHead `RH_RF69` driver
+///
+/// See `Rfm69Config::radio_head`. To exchange packets with nodes using that library, the radio must
+/// use the codec `FrameFormat::RadioHead`. For `RHReliableDatagram` acks, use `MacConfig::radio_head`.
+///
+/// Payload encryption is enabled, if an aes key is given, like `RH_RF69::setEncryptionKey`.
+pub async fn radio_head_defaults<SPI, RESET, DIO0, DELAY, CODEC, E>(
+    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    modem: RadioHeadModem,
+    frequency: u32,
+    aes_key: Option<&[u8; 16]>,
+) -> Result<Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, Error<E, RESET::Error, DIO0::Error>>
 where
     SPI: SpiDevice<u8, Error = E>,
     RESET: OutputPin,
     DIO0: InputPin + Wait,
     DELAY: DelayUs,
+    CODEC: FrameCodec,
 {
     rfm.reset().await?;
-    rfm.set_mode(OpMode::Standby).await?;
-    rfm.modulation(Modulation {
-        data_mode: DataMode::Packet,
-        modulation_type: ModulationType::Fsk,
-        shaping: ModulationShaping::Shaping00,
-    })
-    .await?;
-    rfm.bit_rate(55_555).await?;
-    rfm.fdev(50_000).await?;
-    rfm.rx_bw(RxBw {
-        dcc_cutoff: DccCutoff::Percent4,
-        rx_bw: RxBwFsk::Khz125dot0,
-    })
-    .await?;
-    rfm.preamble_length(3).await?;
-    rfm.sync(&[0x2d, network_id]).await?;
-    rfm.packet(PacketConfig {
-        format: PacketFormat::Variable(66),
-        dc: PacketDc::None,
-        filtering: PacketFiltering::None,
-        crc: true,
-        interpacket_rx_delay: InterPacketRxDelay::Delay2Bits,
-        auto_rx_restart: true,
-    })
-    .await?;
-    rfm.fifo_mode(FifoMode::NotEmpty).await?;
-    rfm.lna(LnaConfig {
-        zin: LnaImpedance::Ohm200,
-        gain_select: LnaGain::AgcLoop,
-    })
-    .await?;
-    rfm.rssi_threshold(220).await?;
-    rfm.frequency(frequency).await?;
-    rfm.continuous_dagc(ContinuousDagc::ImprovedMarginAfcLowBetaOn0).await?;
-    //Timer::after(Duration::from_millis(1)).await;
+    rfm.apply(&Rfm69Config::radio_head(modem, frequency).aes_key(aes_key))
+        .await?;
     rfm.set_mode(OpMode::Sleep).await?;
     Ok(rfm)
 }
 
 /// Custom configuration (gfsk, 100kBit/sec)
 ///
-/// This uses gfsk to reduce the used bandwidth and a 100kBit/sec data rate.
-/// Otherwise it is similar to the Low Power Lab configuration.
-pub async fn my_defaults<SPI, RESET, DIO0, DELAY, E>(
-    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY>,
+/// See `Rfm69Config::my_defaults`.
+///
+/// Payload encryption is enabled, if an aes key is given. The output power is +13 dBm with the
+/// power amplifiers of the configured variant.
+pub async fn my_defaults<SPI, RESET, DIO0, DELAY, CODEC, E>(
+    mut rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
     network_id: u8,
     frequency: u32,
-) -> Result<Rfm69<SPI, RESET, DIO0, DELAY>, Error<E, RESET::Error, DIO0::Error>>
+    aes_key: Option<&[u8; 16]>,
+) -> Result<Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, Error<E, RESET::Error, DIO0::Error>>
 where
     SPI: SpiDevice<u8, Error = E>,
     RESET: OutputPin,
     DIO0: InputPin + Wait,
     DELAY: DelayUs,
+    CODEC: FrameCodec,
 {
     rfm.reset().await?;
-    rfm.set_mode(OpMode::Standby).await?;
-    rfm.modulation(Modulation {
-        data_mode: DataMode::Packet,
-        modulation_type: ModulationType::Fsk,
-        shaping: ModulationShaping::Shaping10, // gfsk with bt = 0.5
-    })
-    .await?;
-    rfm.bit_rate(100_000).await?;
-    rfm.fdev(50_000).await?;
-    rfm.rx_bw(RxBw {
-        dcc_cutoff: DccCutoff::Percent4,
-        rx_bw: RxBwFsk::Khz125dot0,
-    })
-    .await?;
-    rfm.preamble_length(3).await?;
-    rfm.sync(&[0x2d, network_id]).await?;
-    rfm.packet(PacketConfig {
-        format: PacketFormat::Variable(66),
-        dc: PacketDc::None,
-        filtering: PacketFiltering::None,
-        crc: true,
-        interpacket_rx_delay: InterPacketRxDelay::Delay2Bits,
-        auto_rx_restart: true,
-    })
-    .await?;
-    rfm.fifo_mode(FifoMode::NotEmpty).await?;
-    rfm.lna(LnaConfig {
-        zin: LnaImpedance::Ohm50,
-        gain_select: LnaGain::AgcLoop,
-    })
-    .await?;
-    rfm.rssi_threshold(220).await?;
-    rfm.frequency(frequency).await?;
-    rfm.continuous_dagc(ContinuousDagc::ImprovedMarginAfcLowBetaOn0).await?;
-    //Timer::after(Duration::from_millis(1)).await;
+    rfm.apply(&Rfm69Config::my_defaults(network_id, frequency).aes_key(aes_key))
+        .await?;
     rfm.set_mode(OpMode::Sleep).await?;
     Ok(rfm)
 }
@@ -1,3 +1,5 @@
+use crate::config::ConfigError;
+
 /// Error for rfm69 transceiver
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
@@ -7,6 +9,41 @@ pub enum Error<SPI, RESET, DIO0> {
     Reset(RESET),
     SPI(SPI),
     DIO0(DIO0),
+    /// Error of the dio1 pin, which has the same type as the dio0 pin (see `Rfm69::with_dio1`)
+    DIO1(DIO0),
     SyncSize,
+    FdevRange,
+    PacketSize,
+    OutputPower,
     WrongPacketFormat,
+    FifoOverrun,
+    Config(ConfigError),
+    RegisterValue(u8),
+    Timeout(WaitState),
+    Recovered(RecoveryCause),
+}
+
+/// State of the radio that was awaited when a timeout occurred
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WaitState {
+    ModeReady,
+    PacketSent,
+    PayloadReady,
+    FifoLevel,
+    FeiDone,
+}
+
+/// Reason why the radio was reset and reconfigured by the supervisor
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecoveryCause {
+    /// The version register contained an unexpected value, e.g. the spi bus is not working
+    Version(u8),
+    /// The radio is not in the mode it was set to, or the mode is not ready
+    StuckMode,
+    /// The configuration registers lost their content, e.g. after a brownout
+    ConfigLost,
+    /// Several waits for the radio timed out in a row
+    Timeouts,
 }
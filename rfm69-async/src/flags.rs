@@ -1,22 +1,171 @@
-#[derive(Debug, Clone, Copy)]
+/// Control byte of a packet
+///
+/// Layout of version 1:
+///
+/// | Bit | Meaning                                           |
+/// |-----|---------------------------------------------------|
+/// | 7-6 | Protocol version                                  |
+/// | 5   | Ack request, the receiver must send an ack        |
+/// | 4   | Ack response, the packet confirms a received one  |
+/// | 3   | More fragments follow                             |
+/// | 2-0 | Reserved, sent as 0                               |
+///
+/// Later versions keep bits 5 to 3 and may only assign the reserved bits. Version 0 is the byte of
+/// earlier releases: 0 is a packet without ack, 1 is an ack and 2 to 4 request an ack.
+///
+/// The byte is stored as is, so every value round trips unchanged, including unknown versions and
+/// reserved bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum Flags {
-    None,
-    Ack(u8),
-}
+pub struct Flags(u8);
+
 impl Flags {
-    pub(crate) fn from_u8(flags: u8) -> Flags {
-        match flags {
-            0 => Self::None,
-            1..=4 => Self::Ack(flags - 1),
-            _ => Self::None,
+    /// Protocol version of the packets that are created by this crate
+    pub const VERSION: u8 = 1;
+
+    const VERSION_SHIFT: u8 = 6;
+    const ACK_REQUEST: u8 = 1 << 5;
+    const ACK_RESPONSE: u8 = 1 << 4;
+    const MORE_FRAGMENTS: u8 = 1 << 3;
+    const RESERVED: u8 = 0x07;
+
+    /// Returns the flags of the current version without any bit set
+    pub const fn new() -> Self {
+        Self(Self::VERSION << Self::VERSION_SHIFT)
+    }
+
+    pub const fn from_u8(flags: u8) -> Self {
+        Self(flags)
+    }
+
+    pub const fn as_u8(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn version(&self) -> u8 {
+        self.0 >> Self::VERSION_SHIFT
+    }
+
+    pub const fn ack_requested(&self) -> bool {
+        match self.version() {
+            0 => matches!(self.0, 2..=4),
+            _ => self.0 & Self::ACK_REQUEST != 0,
         }
     }
 
-    pub(crate) fn as_u8(&self) -> u8 {
-        match self {
-            Self::None => 0,
-            Self::Ack(retries) => *retries + 1,
+    pub const fn is_ack(&self) -> bool {
+        match self.version() {
+            0 => self.0 == 1,
+            _ => self.0 & Self::ACK_RESPONSE != 0,
         }
     }
+
+    pub const fn more_fragments(&self) -> bool {
+        match self.version() {
+            0 => false,
+            _ => self.0 & Self::MORE_FRAGMENTS != 0,
+        }
+    }
+
+    /// Returns the reserved bits 2 to 0
+    pub const fn reserved(&self) -> u8 {
+        match self.version() {
+            0 => 0,
+            _ => self.0 & Self::RESERVED,
+        }
+    }
+
+    pub const fn with_ack_request(self, request: bool) -> Self {
+        self.with_bit(Self::ACK_REQUEST, request)
+    }
+
+    pub const fn with_ack_response(self, response: bool) -> Self {
+        self.with_bit(Self::ACK_RESPONSE, response)
+    }
+
+    pub const fn with_more_fragments(self, more: bool) -> Self {
+        self.with_bit(Self::MORE_FRAGMENTS, more)
+    }
+
+    /// Sets a bit of the version 1 layout, a version 0 byte is converted to version 1 first
+    const fn with_bit(self, bit: u8, set: bool) -> Self {
+        let flags = if self.version() == 0 {
+            let mut flags = Self::new().0;
+            if self.ack_requested() {
+                flags |= Self::ACK_REQUEST;
+            }
+            if self.is_ack() {
+                flags |= Self::ACK_RESPONSE;
+            }
+            flags
+        } else {
+            self.0
+        };
+        if set {
+            Self(flags | bit)
+        } else {
+            Self(flags & !bit)
+        }
+    }
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<u8> for Flags {
+    fn from(flags: u8) -> Self {
+        Self::from_u8(flags)
+    }
+}
+
+impl From<Flags> for u8 {
+    fn from(flags: Flags) -> Self {
+        flags.as_u8()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_byte_round_trips() {
+        for byte in 0..=u8::MAX {
+            let flags = Flags::from(byte);
+            assert_eq!(u8::from(flags), byte);
+            assert_eq!(flags.version(), byte >> 6);
+            if flags.version() != 0 {
+                assert_eq!(flags.ack_requested(), byte & 0x20 != 0);
+                assert_eq!(flags.is_ack(), byte & 0x10 != 0);
+                assert_eq!(flags.more_fragments(), byte & 0x08 != 0);
+                assert_eq!(flags.reserved(), byte & 0x07);
+            }
+        }
+    }
+
+    #[test]
+    fn version_0_is_mapped() {
+        for byte in 0..0x40 {
+            let flags = Flags::from(byte);
+            assert_eq!(flags.ack_requested(), (2..=4).contains(&byte));
+            assert_eq!(flags.is_ack(), byte == 1);
+            assert!(!flags.more_fragments());
+            assert_eq!(flags.reserved(), 0);
+        }
+
+        // Setting a bit converts the byte to version 1
+        assert_eq!(Flags::from(0).with_more_fragments(false), Flags::new());
+        assert_eq!(
+            Flags::from(1).with_more_fragments(false),
+            Flags::new().with_ack_response(true)
+        );
+        assert_eq!(
+            Flags::from(3).with_more_fragments(true),
+            Flags::new().with_ack_request(true).with_more_fragments(true)
+        );
+        assert_eq!(Flags::from(4).with_ack_request(false).as_u8(), 0x40);
+    }
 }
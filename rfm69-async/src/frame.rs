@@ -0,0 +1,395 @@
+//! Layouts of the frames in the fifo
+//!
+//! `Rfm69` converts frames with a `FrameCodec` when they are sent and received. `FrameFormat` is the
+//! default codec for `Packet`s, `Raw` passes the frames through unchanged. The length byte of variable
+//! length packets is added and removed by `Rfm69`, it is not part of the frames.
+
+use heapless::Vec;
+
+use crate::address::Address;
+use crate::flags::Flags;
+use crate::packet::{Packet, PacketError};
+
+/// Ack sent bit of the LowPowerLab control byte
+const LPL_CTL_SENDACK: u8 = 0x80;
+/// Ack requested bit of the LowPowerLab control byte
+const LPL_CTL_REQACK: u8 = 0x40;
+/// Bits 9 and 8 of the target address
+const LPL_CTL_TARGET_HIGH: u8 = 0x0c;
+/// Bits 9 and 8 of the sender address
+const LPL_CTL_SENDER_HIGH: u8 = 0x03;
+/// Header after the length byte: target, sender, control byte
+const LPL_HEADER_LENGTH: usize = 3;
+/// Largest payload the LowPowerLab library accepts (`RF69_MAX_DATA_LEN`)
+const LPL_MAX_DATA_LENGTH: usize = 61;
+/// Largest address of the 10 bit addressing
+const LPL_MAX_ADDRESS: u16 = 0x3ff;
+
+/// Ack bit of the RadioHead flags (`RH_FLAGS_ACK`)
+const RH_FLAGS_ACK: u8 = 0x80;
+/// Header after the length byte: to, from, id, flags
+const RH_HEADER_LENGTH: usize = 4;
+/// Largest payload the RadioHead driver accepts (`RH_RF69_MAX_MESSAGE_LEN`)
+const RH_MAX_DATA_LENGTH: usize = 60;
+
+/// Largest frame, the length byte of variable length packets is limited to 255
+const MAX_FRAME_LENGTH: usize = 255;
+
+/// Converts between the frames of `Rfm69::send` and `Rfm69::recv` and their bytes
+///
+/// The bytes exclude the length byte of variable length packets. In fixed length mode, `Rfm69` pads
+/// shorter frames with zeros, so `decode` gets the complete fixed length.
+pub trait FrameCodec {
+    /// Frame that is sent and received
+    type Frame;
+
+    /// Writes the bytes of the frame and returns their amount
+    fn encode(&self, frame: &Self::Frame, raw: &mut [u8; MAX_FRAME_LENGTH]) -> Result<usize, PacketError>;
+
+    /// Parses the bytes of a received frame
+    fn decode(&self, raw: &[u8], rx: RxInfo) -> Result<Self::Frame, PacketError>;
+}
+
+/// Signal measurements of a received frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxInfo {
+    pub rssi: i16,
+    /// Frequency correction in Hz the afc applied while receiving
+    pub afc: i32,
+    /// Frequency error in Hz of the last fei measurement
+    pub fei: i32,
+}
+
+/// Layout of the frames, that are written to and read from the fifo
+///
+/// The LowPowerLab and RadioHead libraries use variable length packets. In fixed length mode, the data
+/// of received packets includes the padding of shorter frames.
+///
+/// All formats start with the destination, so the radio can drop frames for other nodes (see
+/// `Rfm69Config::address_filtering`). The radio compares a single byte, so only the low 8 bits of the
+/// LowPowerLab addresses are checked by the radio.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameFormat {
+    /// Format of this crate: length, destination, source, flags, sequence number, data
+    ///
+    /// Addresses are one byte, 255 is the broadcast address.
+    #[default]
+    Native,
+    /// Format of the LowPowerLab RFM69 library: length, target, sender, control byte, data
+    ///
+    /// See `<https://github.com/LowPowerLab/RFM69>`. Addresses have 10 bits, bits 9 and 8 are stored
+    /// in the control byte (target in bits 3-2, sender in bits 1-0). 0 is the broadcast address, a
+    /// frame from sender 0 is rejected. Bit 7 of the control byte marks an ack, bit 6 requests one.
+    /// There are no sequence numbers and the payload is limited to 61 bytes.
+    ///
+    /// Frames as a Moteino sends them (fifo content including the length byte):
+    ///
+    /// | Frame                                   | Meaning                                   |
+    /// |-----------------------------------------|-------------------------------------------|
+    /// | `05 01 02 40 48 69`                     | 2 sends "Hi" to 1 and requests an ack     |
+    /// | `03 02 01 80`                           | 1 acks to 2                               |
+    /// | `04 00 02 00 55`                        | 2 broadcasts 0x55                         |
+    /// | `04 e8 01 0e aa`                        | 513 sends 0xaa to 1000                    |
+    LowPowerLab,
+    /// Format of the RadioHead `RH_RF69` driver: length, to, from, id, flags, data
+    ///
+    /// See `<https://www.airspayce.com/mikem/arduino/RadioHead/>`. Addresses are one byte, 255 is
+    /// the broadcast address. The id is the sequence number. Bit 7 of the flags marks an ack, the
+    /// other bits are not used. The payload is limited to 60 bytes.
+    ///
+    /// RadioHead has no ack request: `RHReliableDatagram` acks every unicast frame, so those are
+    /// decoded with the ack request flag set.
+    ///
+    /// Frames as `RHReliableDatagram` sends them (fifo content including the length byte):
+    ///
+    /// | Frame                                   | Meaning                                   |
+    /// |-----------------------------------------|-------------------------------------------|
+    /// | `06 02 01 07 00 48 69`                  | 1 sends "Hi" to 2 with id 7               |
+    /// | `05 01 02 07 80 21`                     | 2 acks id 7 to 1                          |
+    /// | `05 ff 01 08 00 55`                     | 1 broadcasts 0x55 with id 8               |
+    RadioHead,
+}
+
+impl FrameCodec for FrameFormat {
+    type Frame = Packet;
+
+    fn encode(&self, packet: &Packet, raw: &mut [u8; MAX_FRAME_LENGTH]) -> Result<usize, PacketError> {
+        match self {
+            Self::Native => packet.to_slice(raw),
+            Self::LowPowerLab => encode_low_power_lab(packet, raw),
+            Self::RadioHead => encode_radio_head(packet, raw),
+        }
+    }
+
+    fn decode(&self, raw: &[u8], rx: RxInfo) -> Result<Packet, PacketError> {
+        let mut packet = match self {
+            Self::Native => Packet::from_rx_data(raw.len() as u8, raw, rx.rssi),
+            Self::LowPowerLab => decode_low_power_lab(raw, rx.rssi),
+            Self::RadioHead => decode_radio_head(raw, rx.rssi),
+        }?;
+        packet.afc = Some(rx.afc);
+        packet.fei = Some(rx.fei);
+        Ok(packet)
+    }
+}
+
+/// Codec that passes the frames through unchanged
+///
+/// Used to implement other protocols on top of the driver. In fixed length mode the frames are the
+/// complete fifo content, in variable length mode the fifo content after the length byte.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Raw;
+
+/// Bytes of a frame, see `Raw`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RawFrame {
+    pub data: Vec<u8, MAX_FRAME_LENGTH>,
+    /// Signal measurements, if the frame was received
+    pub rx: Option<RxInfo>,
+}
+
+impl RawFrame {
+    pub fn new(data: &[u8]) -> Result<Self, PacketError> {
+        Ok(Self {
+            data: Vec::from_slice(data).map_err(|_| PacketError::DataTooLong)?,
+            rx: None,
+        })
+    }
+}
+
+impl FrameCodec for Raw {
+    type Frame = RawFrame;
+
+    fn encode(&self, frame: &RawFrame, raw: &mut [u8; MAX_FRAME_LENGTH]) -> Result<usize, PacketError> {
+        raw[..frame.data.len()].copy_from_slice(&frame.data);
+        Ok(frame.data.len())
+    }
+
+    fn decode(&self, raw: &[u8], rx: RxInfo) -> Result<RawFrame, PacketError> {
+        Ok(RawFrame {
+            data: Vec::from_slice(raw).map_err(|_| PacketError::DataTooLong)?,
+            rx: Some(rx),
+        })
+    }
+}
+
+fn low_power_lab_address(address: Address) -> Result<u16, PacketError> {
+    match address {
+        Address::Broadcast => Ok(0),
+        Address::Unicast(0) => Err(PacketError::Address),
+        Address::Unicast(addr) if addr > LPL_MAX_ADDRESS => Err(PacketError::Address),
+        Address::Unicast(addr) => Ok(addr),
+    }
+}
+
+fn encode_low_power_lab(packet: &Packet, raw: &mut [u8; MAX_FRAME_LENGTH]) -> Result<usize, PacketError> {
+    if packet.data.len() > LPL_MAX_DATA_LENGTH {
+        return Err(PacketError::DataTooLong);
+    }
+    let target = low_power_lab_address(packet.dst)?;
+    let sender = low_power_lab_address(packet.src)?;
+    // The broadcast address is no valid sender
+    if sender == 0 {
+        return Err(PacketError::Address);
+    }
+
+    // The library sets only one of the ack bits, an ack never requests another one
+    let mut ctl = if packet.flags.is_ack() {
+        LPL_CTL_SENDACK
+    } else if packet.flags.ack_requested() {
+        LPL_CTL_REQACK
+    } else {
+        0
+    };
+    ctl |= ((target >> 6) as u8 & LPL_CTL_TARGET_HIGH) | ((sender >> 8) as u8 & LPL_CTL_SENDER_HIGH);
+
+    raw[0] = target as u8;
+    raw[1] = sender as u8;
+    raw[2] = ctl;
+    raw[3..3 + packet.data.len()].copy_from_slice(packet.data.as_slice());
+    Ok(packet.data.len() + LPL_HEADER_LENGTH)
+}
+
+fn decode_low_power_lab(raw: &[u8], rssi: i16) -> Result<Packet, PacketError> {
+    if raw.len() < LPL_HEADER_LENGTH {
+        return Err(PacketError::DataTooShort);
+    }
+    let ctl = raw[2];
+    let target = u16::from(raw[0]) | (u16::from(ctl & LPL_CTL_TARGET_HIGH) << 6);
+    let sender = u16::from(raw[1]) | (u16::from(ctl & LPL_CTL_SENDER_HIGH) << 8);
+    // The broadcast address is no valid sender
+    if sender == 0 {
+        return Err(PacketError::Address);
+    }
+    let flags = Flags::new()
+        .with_ack_response(ctl & LPL_CTL_SENDACK != 0)
+        .with_ack_request(ctl & LPL_CTL_REQACK != 0);
+    Ok(Packet {
+        src: Address::Unicast(sender),
+        dst: match target {
+            0 => Address::Broadcast,
+            addr => Address::Unicast(addr),
+        },
+        flags,
+        seq: None,
+        data: Vec::from_slice(&raw[LPL_HEADER_LENGTH..]).map_err(|_| PacketError::DataTooLong)?,
+        rssi: Some(rssi),
+        afc: None,
+        fei: None,
+    })
+}
+
+fn encode_radio_head(packet: &Packet, raw: &mut [u8; MAX_FRAME_LENGTH]) -> Result<usize, PacketError> {
+    if packet.data.len() > RH_MAX_DATA_LENGTH {
+        return Err(PacketError::DataTooLong);
+    }
+    let to = packet.dst.as_u8().ok_or(PacketError::Address)?;
+    let from = packet.src.as_u8().ok_or(PacketError::Address)?;
+    let flags = if packet.flags.is_ack() { RH_FLAGS_ACK } else { 0 };
+
+    raw[0] = to;
+    raw[1] = from;
+    raw[2] = packet.seq.unwrap_or(0);
+    raw[3] = flags;
+    raw[4..4 + packet.data.len()].copy_from_slice(packet.data.as_slice());
+    Ok(packet.data.len() + RH_HEADER_LENGTH)
+}
+
+fn decode_radio_head(raw: &[u8], rssi: i16) -> Result<Packet, PacketError> {
+    if raw.len() < RH_HEADER_LENGTH {
+        return Err(PacketError::DataTooShort);
+    }
+    let dst = Address::from_u8(raw[0]);
+    let is_ack = raw[3] & RH_FLAGS_ACK != 0;
+    let flags = Flags::new()
+        .with_ack_response(is_ack)
+        .with_ack_request(!is_ack && dst != Address::Broadcast);
+    Ok(Packet {
+        src: Address::from_u8(raw[1]),
+        dst,
+        flags,
+        seq: Some(raw[2]),
+        data: Vec::from_slice(&raw[RH_HEADER_LENGTH..]).map_err(|_| PacketError::DataTooLong)?,
+        rssi: Some(rssi),
+        afc: None,
+        fei: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RX: RxInfo = RxInfo {
+        rssi: -50,
+        afc: 0,
+        fei: 0,
+    };
+
+    /// Decodes the fifo content of a documented frame and checks that encoding gives it back
+    fn round_trip(format: FrameFormat, fifo: &[u8]) -> Packet {
+        let (length, frame) = fifo.split_first().unwrap();
+        assert_eq!(usize::from(*length), frame.len());
+        let packet = format.decode(frame, RX).unwrap();
+        let mut raw = [0; MAX_FRAME_LENGTH];
+        let len = format.encode(&packet, &mut raw).unwrap();
+        assert_eq!(&raw[..len], frame);
+        packet
+    }
+
+    #[test]
+    fn low_power_lab_vectors() {
+        let packet = round_trip(FrameFormat::LowPowerLab, &[0x05, 0x01, 0x02, 0x40, 0x48, 0x69]);
+        assert_eq!(packet.src, Address::Unicast(2));
+        assert_eq!(packet.dst, Address::Unicast(1));
+        assert!(packet.flags.ack_requested());
+        assert!(!packet.is_ack());
+        assert_eq!(packet.seq, None);
+        assert_eq!(packet.data.as_slice(), b"Hi");
+
+        let packet = round_trip(FrameFormat::LowPowerLab, &[0x03, 0x02, 0x01, 0x80]);
+        assert_eq!(packet.src, Address::Unicast(1));
+        assert_eq!(packet.dst, Address::Unicast(2));
+        assert!(packet.is_ack());
+        assert!(!packet.flags.ack_requested());
+        assert!(packet.data.is_empty());
+
+        let packet = round_trip(FrameFormat::LowPowerLab, &[0x04, 0x00, 0x02, 0x00, 0x55]);
+        assert_eq!(packet.src, Address::Unicast(2));
+        assert_eq!(packet.dst, Address::Broadcast);
+        assert!(!packet.flags.ack_requested());
+        assert_eq!(packet.data.as_slice(), &[0x55]);
+
+        let packet = round_trip(FrameFormat::LowPowerLab, &[0x04, 0xe8, 0x01, 0x0e, 0xaa]);
+        assert_eq!(packet.src, Address::Unicast(513));
+        assert_eq!(packet.dst, Address::Unicast(1000));
+        assert_eq!(packet.data.as_slice(), &[0xaa]);
+    }
+
+    #[test]
+    fn low_power_lab_limits() {
+        let mut raw = [0; MAX_FRAME_LENGTH];
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(1024), Flags::new(), &[]).unwrap();
+        assert!(matches!(
+            FrameFormat::LowPowerLab.encode(&packet, &mut raw),
+            Err(PacketError::Address)
+        ));
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[0; 62]).unwrap();
+        assert!(matches!(
+            FrameFormat::LowPowerLab.encode(&packet, &mut raw),
+            Err(PacketError::DataTooLong)
+        ));
+        assert!(matches!(
+            FrameFormat::LowPowerLab.decode(&[0x02], RX),
+            Err(PacketError::DataTooShort)
+        ));
+    }
+
+    #[test]
+    fn low_power_lab_sender_0_is_rejected() {
+        let mut raw = [0; MAX_FRAME_LENGTH];
+        let packet = Packet::new(Address::Broadcast, Address::Unicast(1), Flags::new(), &[]).unwrap();
+        assert!(matches!(
+            FrameFormat::LowPowerLab.encode(&packet, &mut raw),
+            Err(PacketError::Address)
+        ));
+        assert!(matches!(
+            FrameFormat::LowPowerLab.decode(&[0x01, 0x00, 0x00, 0x55], RX),
+            Err(PacketError::Address)
+        ));
+        assert!(matches!(
+            FrameFormat::LowPowerLab.decode(&[0x00, 0x00, 0x00, 0x55], RX),
+            Err(PacketError::Address)
+        ));
+    }
+
+    #[test]
+    fn radio_head_vectors() {
+        let packet = round_trip(FrameFormat::RadioHead, &[0x06, 0x02, 0x01, 0x07, 0x00, 0x48, 0x69]);
+        assert_eq!(packet.src, Address::Unicast(1));
+        assert_eq!(packet.dst, Address::Unicast(2));
+        assert_eq!(packet.seq, Some(7));
+        // RHReliableDatagram acks every unicast frame
+        assert!(packet.flags.ack_requested());
+        assert_eq!(packet.data.as_slice(), b"Hi");
+
+        let packet = round_trip(FrameFormat::RadioHead, &[0x05, 0x01, 0x02, 0x07, 0x80, 0x21]);
+        assert_eq!(packet.src, Address::Unicast(2));
+        assert_eq!(packet.dst, Address::Unicast(1));
+        assert_eq!(packet.seq, Some(7));
+        assert!(packet.is_ack());
+        assert!(!packet.flags.ack_requested());
+        assert_eq!(packet.data.as_slice(), &[0x21]);
+
+        let packet = round_trip(FrameFormat::RadioHead, &[0x05, 0xff, 0x01, 0x08, 0x00, 0x55]);
+        assert_eq!(packet.src, Address::Unicast(1));
+        assert_eq!(packet.dst, Address::Broadcast);
+        assert_eq!(packet.seq, Some(8));
+        assert!(!packet.flags.ack_requested());
+        assert_eq!(packet.data.as_slice(), &[0x55]);
+    }
+}
@@ -1,18 +1,24 @@
 #![no_std]
 #![feature(type_alias_impl_trait)]
+#![feature(async_fn_in_trait)]
 
 mod address;
+pub mod blocking;
 pub mod config;
 mod error;
 mod flags;
+mod frame;
 mod packet;
-mod registers;
+pub mod registers;
 mod rfm;
+#[cfg(feature = "sim")]
+pub mod sim;
 
 pub mod mac;
 
 pub use address::Address;
-pub use error::Error;
+pub use error::{Error, RecoveryCause, WaitState};
 pub use flags::Flags;
-pub use packet::Packet;
+pub use frame::{FrameCodec, FrameFormat, Raw, RawFrame, RxInfo};
+pub use packet::{Packet, PacketError};
 pub use rfm::Rfm69;
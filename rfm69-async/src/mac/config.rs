@@ -0,0 +1,193 @@
+//! Timing parameters of the mac layer
+
+use embedded_hal_1::digital::{InputPin, OutputPin};
+use embedded_hal_async::delay::DelayUs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::config::Rfm69Config;
+use crate::registers::{PacketFormat, RxBwFreq};
+use crate::{FrameCodec, Rfm69};
+
+/// Time the sender needs after `send` returned to be ready for the ack, and the time the receiver
+/// needs to process a packet before it can reply
+const TURNAROUND_US: u32 = 10_000;
+
+/// Added to the expected arrival time of an ack, covers the software latency on both sides
+const ACK_MARGIN_US: u32 = 40_000;
+
+/// Shortest delay between two attempts
+const MIN_RETRY_DELAY_US: u32 = 150_000;
+
+/// Attempts of packets that request an ack
+const RETRIES: u8 = 3;
+
+/// Ack timeout of `RHReliableDatagram` (`RH_DEFAULT_TIMEOUT`)
+const RH_ACK_TIMEOUT_US: u32 = 200_000;
+
+/// Retransmissions of `RHReliableDatagram` (`RH_DEFAULT_RETRIES`)
+const RH_RETRIES: u8 = 3;
+
+/// Length of the packet header (dst, src, flags, seq), which is the complete ack
+const HEADER_LENGTH: u32 = 4;
+
+/// Timing of the radio after power on reset: 4.8 kBit/sec, 3 preamble bytes, 4 sync bytes,
+/// fixed length packets with 64 bytes and crc
+const RESET_TIMING: Airtime = Airtime {
+    bit_rate: 4_800,
+    overhead: 3 + 4 + 2,
+    max_length: 64,
+    fixed_length: true,
+    aes: false,
+};
+
+/// Parameters to calculate the time a packet is on air
+#[derive(Clone, Copy)]
+struct Airtime {
+    bit_rate: u32,
+    /// Bytes sent in addition to the fifo content: preamble, sync words and crc
+    overhead: u32,
+    /// Largest fifo content including the length byte
+    max_length: u32,
+    /// Every packet has the largest length
+    fixed_length: bool,
+    aes: bool,
+}
+
+impl Airtime {
+    fn from_config<T: RxBwFreq>(config: &Rfm69Config<T>) -> Self {
+        let crc = if config.packet.crc { 2 } else { 0 };
+        let (max_length, fixed_length) = match config.packet.format {
+            PacketFormat::Variable(length) => (u32::from(length) + 1, false),
+            PacketFormat::Fixed(length) => (u32::from(length), true),
+        };
+        Self {
+            bit_rate: config.bit_rate,
+            overhead: u32::from(config.preamble_length) + config.sync.len() as u32 + crc,
+            max_length,
+            fixed_length,
+            aes: config.aes_key.is_some(),
+        }
+    }
+
+    /// Returns the time on air of a packet with the given fifo content length
+    ///
+    /// Packets are padded to the fixed length in fixed length mode.
+    fn us(&self, length: u32) -> u32 {
+        let length = if self.fixed_length { self.max_length } else { length };
+        // The aes engine pads the message to full blocks, the length byte is not encrypted
+        let length = if self.aes {
+            1 + ((length.saturating_sub(1) + 15) & !15)
+        } else {
+            length
+        };
+        let bits = u64::from(self.overhead + length) * 8;
+        (bits * 1_000_000 / u64::from(self.bit_rate)) as u32
+    }
+}
+
+/// Timing parameters of the acknowledged delivery
+///
+/// Use `MacConfig::for_radio` or `MacConfig::from_config` to derive the parameters from the
+/// bitrate and the packet airtime of the radio configuration, then adjust single fields if needed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MacConfig {
+    /// Delay before an ack is sent, so the original sender could switch from tx to rx mode
+    pub ack_tx_delay_us: u32,
+    /// Time to wait for an ack after a packet was sent
+    pub ack_timeout_us: u32,
+    /// Upper limit of the random time added to each ack timeout
+    pub ack_timeout_jitter_us: u32,
+    /// Delay before a packet is sent again, after the ack timed out
+    pub retry_delay_us: u32,
+    /// Upper limit of the random time added to each retry delay
+    ///
+    /// It keeps two senders, whose packets collided, from colliding again.
+    pub retry_jitter_us: u32,
+    /// Attempts of packets that request an ack, 0 sends them once without waiting for the ack
+    pub retries: u8,
+}
+
+impl MacConfig {
+    /// Returns the timing for the configuration last applied to the radio
+    ///
+    /// If no configuration was applied, the timing of the radio after reset is used.
+    pub fn for_radio<SPI, RESET, DIO0, DELAY, CODEC, E>(rfm: &Rfm69<SPI, RESET, DIO0, DELAY, CODEC>) -> Self
+    where
+        SPI: SpiDevice<u8, Error = E>,
+        RESET: OutputPin,
+        DIO0: InputPin + Wait,
+        DELAY: DelayUs,
+        CODEC: FrameCodec,
+    {
+        match rfm.config() {
+            Some(config) => Self::from_config(config),
+            None => Self::from_airtime(RESET_TIMING),
+        }
+    }
+
+    /// Returns the timing for the given radio configuration
+    pub fn from_config<T: RxBwFreq>(config: &Rfm69Config<T>) -> Self {
+        Self::from_airtime(Airtime::from_config(config))
+    }
+
+    /// Returns the timing of RadioHead's `RHReliableDatagram` with its default settings
+    ///
+    /// Like `RHReliableDatagram::sendtoWait`, each ack timeout is randomised between 200 ms and
+    /// 400 ms, then the packet is sent again without a further delay. RadioHead counts retries after
+    /// the first attempt, so there are 4 attempts. Use it with `FrameFormat::RadioHead` and send
+    /// unicast packets with an ack request, because RadioHead acks every unicast packet.
+    pub fn radio_head() -> Self {
+        Self {
+            ack_tx_delay_us: TURNAROUND_US,
+            ack_timeout_us: RH_ACK_TIMEOUT_US,
+            ack_timeout_jitter_us: RH_ACK_TIMEOUT_US,
+            retry_delay_us: 0,
+            retry_jitter_us: 0,
+            retries: RH_RETRIES + 1,
+        }
+    }
+
+    fn from_airtime(airtime: Airtime) -> Self {
+        // The ack is a header only packet, plus the length byte
+        let ack_us = airtime.us(HEADER_LENGTH + 1);
+        let max_packet_us = airtime.us(airtime.max_length);
+        Self {
+            ack_tx_delay_us: TURNAROUND_US,
+            ack_timeout_us: TURNAROUND_US + ack_us + ACK_MARGIN_US,
+            ack_timeout_jitter_us: 0,
+            retry_delay_us: MIN_RETRY_DELAY_US + 2 * max_packet_us,
+            retry_jitter_us: max_packet_us + ack_us,
+            retries: RETRIES,
+        }
+    }
+
+    /// Returns the ack timeout with a random jitter
+    ///
+    /// The seed should differ between nodes and attempts, e.g. the time mixed with the own address.
+    pub(crate) fn jittered_ack_timeout_us(&self, seed: u64) -> u32 {
+        jittered(self.ack_timeout_us, self.ack_timeout_jitter_us, seed)
+    }
+
+    /// Returns the retry delay with a random jitter, see `jittered_ack_timeout_us`
+    pub(crate) fn jittered_retry_delay_us(&self, seed: u64) -> u32 {
+        jittered(self.retry_delay_us, self.retry_jitter_us, seed)
+    }
+}
+
+/// Adds a random time below `jitter` to `us`
+fn jittered(us: u32, jitter: u32, seed: u64) -> u32 {
+    if jitter == 0 {
+        return us;
+    }
+    us.saturating_add((splitmix64(seed) % u64::from(jitter)) as u32)
+}
+
+/// Scrambles the seed, see `<https://prng.di.unimi.it/splitmix64.c>`
+pub(super) fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
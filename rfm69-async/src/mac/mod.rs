@@ -0,0 +1,385 @@
+//! Simple mac layer with acknowledged delivery
+//!
+//! The delays and timeouts are provided by a `MacTimer`, so the mac layer works with embassy
+//! (`EmbassyTimer`, feature `embassy`) and any other executor (`DelayTimer`).
+
+use embedded_hal_1::digital::{InputPin, OutputPin};
+use embedded_hal_async::delay::DelayUs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::spi::SpiDevice;
+
+use crate::config::Rfm69Config;
+use crate::registers::RxBwFreq;
+use crate::{Address, Error, Flags, FrameCodec, FrameFormat, Packet, Rfm69};
+
+mod config;
+mod queue;
+mod state;
+mod timer;
+
+use config::splitmix64;
+pub use config::MacConfig;
+pub use queue::RxQueue;
+pub use state::MacState;
+#[cfg(feature = "embassy")]
+pub use timer::EmbassyTimer;
+pub use timer::{Clock, DelayTimer, MacTimer};
+
+/// Mac layer of a node, owns the radio and the timer
+///
+/// The radio must be configured already, e.g. with `config::my_defaults`, and use a codec with `Packet`
+/// frames. The timing is derived from that configuration, see `MacConfig::for_radio`.
+pub struct Mac<SPI, RESET, DIO0, DELAY, TIMER, CODEC = FrameFormat> {
+    rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    timer: TIMER,
+    config: MacConfig,
+    state: MacState,
+    address: Address,
+    network_id: u8,
+}
+
+impl<SPI, RESET, DIO0, DELAY, TIMER, CODEC, E> Mac<SPI, RESET, DIO0, DELAY, TIMER, CODEC>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    TIMER: MacTimer,
+    CODEC: FrameCodec<Frame = Packet>,
+{
+    /// Creates the mac layer of the node with the given unicast address
+    ///
+    /// `network_id` is the one the radio was configured with. The first sequence number is derived
+    /// from the current time and the address, use `with_initial_seq` to take it from a random number
+    /// generator instead.
+    pub fn new(rfm: Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, mut timer: TIMER, network_id: u8, address: u16) -> Self {
+        let config = MacConfig::for_radio(&rfm);
+        let initial_seq = splitmix64(timer.now_us() ^ (u64::from(address) << 48)) as u8;
+        Self {
+            rfm,
+            timer,
+            config,
+            state: MacState::new(initial_seq),
+            address: Address::Unicast(address),
+            network_id,
+        }
+    }
+
+    /// Replaces the timing parameters
+    pub fn with_config(mut self, config: MacConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the sequence number the next packet follows, see `MacState::new`
+    pub fn with_initial_seq(mut self, initial_seq: u8) -> Self {
+        self.state = MacState::new(initial_seq);
+        self
+    }
+
+    /// Applies the radio configuration with address filtering for this node and derives the timing
+    ///
+    /// The radio drops frames, that are neither for this node nor broadcasts, so they do not wake the
+    /// mcu. The broadcast address is the one of the configuration, the presets set the one of their
+    /// frame format. Use `rfm` to apply a configuration without filtering.
+    pub async fn apply<T>(&mut self, config: &Rfm69Config<T>) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+    where
+        T: RxBwFreq + Copy,
+    {
+        // The radio compares the low byte of the address
+        let config = config.clone().address_filtering(self.address.as_u16() as u8);
+        self.rfm.apply(&config).await?;
+        self.config = MacConfig::from_config(&config);
+        Ok(())
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn network_id(&self) -> u8 {
+        self.network_id
+    }
+
+    pub fn config(&self) -> &MacConfig {
+        &self.config
+    }
+
+    /// Returns the sequence numbers, duplicates and queued frames
+    pub fn state(&self) -> &MacState {
+        &self.state
+    }
+
+    /// Gives access to the radio, e.g. to supervise it
+    ///
+    /// If the radio configuration is changed, the timing should be updated with `with_config`.
+    pub fn rfm(&mut self) -> &mut Rfm69<SPI, RESET, DIO0, DELAY, CODEC> {
+        &mut self.rfm
+    }
+
+    /// Returns the radio and the timer
+    pub fn release(self) -> (Rfm69<SPI, RESET, DIO0, DELAY, CODEC>, TIMER) {
+        (self.rfm, self.timer)
+    }
+
+    /// Sends a packet without requesting an ack
+    pub async fn send(&mut self, dst: Address, data: &[u8]) -> Result<(), TxError<E, RESET::Error, DIO0::Error>> {
+        let src = self.address;
+        send_packet(
+            &mut self.rfm,
+            &mut self.timer,
+            &self.config,
+            &mut self.state,
+            src,
+            dst,
+            Flags::new(),
+            data,
+        )
+        .await
+    }
+
+    /// Sends a packet and waits for the ack, the packet is repeated up to `MacConfig::retries` times
+    pub async fn send_reliable(
+        &mut self,
+        dst: Address,
+        data: &[u8],
+    ) -> Result<(), TxError<E, RESET::Error, DIO0::Error>> {
+        let src = self.address;
+        let flags = Flags::new().with_ack_request(true);
+        send_packet(
+            &mut self.rfm,
+            &mut self.timer,
+            &self.config,
+            &mut self.state,
+            src,
+            dst,
+            flags,
+            data,
+        )
+        .await
+    }
+
+    /// Sends a packet to all nodes of the network
+    pub async fn broadcast(&mut self, data: &[u8]) -> Result<(), TxError<E, RESET::Error, DIO0::Error>> {
+        self.send(Address::Broadcast, data).await
+    }
+
+    /// Receives the next packet for this node or broadcast and sends the ack, if one is requested
+    ///
+    /// This function is cancel safe, see `Rfm69::recv`.
+    pub async fn recv(&mut self) -> Result<Packet, Error<E, RESET::Error, DIO0::Error>> {
+        receive_packet(
+            &mut self.rfm,
+            &mut self.timer,
+            &self.config,
+            &mut self.state,
+            self.address,
+        )
+        .await
+    }
+}
+
+#[derive(Debug)]
+pub enum TxError<SPI, RESET, DIO0> {
+    AckTimeout,
+    Rfm69Error(Error<SPI, RESET, DIO0>),
+}
+
+/// Sends a packet and waits for the ack, if one is requested
+///
+/// If `flags` request an ack, the packet is sent up to `MacConfig::retries` times. The radio is put
+/// back into standby after each ack timeout. The timeouts and delays are taken from `config`, see
+/// `MacConfig::for_radio`. The packet gets the next sequence number of `state`, only an ack with
+/// that sequence number is accepted. Other frames for `src`, which arrive while waiting for the ack,
+/// are queued in `state` and their requested acks are sent. This function is cancel safe, see
+/// `Rfm69::send` and `Rfm69::recv`.
+#[allow(clippy::too_many_arguments)]
+pub async fn send_packet<SPI, RESET, DIO0, DELAY, TIMER, CODEC, E>(
+    rfm: &mut Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    timer: &mut TIMER,
+    config: &MacConfig,
+    state: &mut MacState,
+    src: Address,
+    dst: Address,
+    flags: Flags,
+    data: &[u8],
+) -> Result<(), TxError<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    TIMER: MacTimer,
+    CODEC: FrameCodec<Frame = Packet>,
+{
+    let seq = state.next_seq();
+    let packet = Packet::new(src, dst, flags, data)
+        .map_err(|_| TxError::Rfm69Error(Error::WrongPacketFormat))?
+        .with_seq(seq);
+
+    let retries = config.retries;
+    if !flags.ack_requested() || retries == 0 {
+        log::info!("Sending packet");
+        rfm.send(&packet).await.map_err(|e| TxError::Rfm69Error(e))
+    } else {
+        for i in 1..=retries {
+            log::info!("Sending packet {i} of {retries} and waiting for ACK");
+            rfm.send(&packet).await.map_err(|e| TxError::Rfm69Error(e))?;
+            // Mix in the own address, so nodes with synchronised clocks differ
+            let seed = timer.now_us() ^ (u64::from(src.as_u16()) << 48) ^ u64::from(i);
+            let deadline = timer.now_us() + u64::from(config.jittered_ack_timeout_us(seed));
+            loop {
+                let remaining = deadline.saturating_sub(timer.now_us()).min(u64::from(u32::MAX)) as u32;
+                let result = timer
+                    .with_timeout(remaining, wait_for_mac_ack(rfm, src, dst, seq))
+                    .await;
+                match result {
+                    Some(Ok(None)) => return Ok(()),
+                    Some(Ok(Some(other))) => {
+                        if let Some(other) = accept_packet(rfm, timer, config, state, src, other)
+                            .await
+                            .map_err(TxError::Rfm69Error)?
+                        {
+                            state.queue().push(other);
+                        }
+                    }
+                    Some(Err(e)) => return Err(TxError::Rfm69Error(e)),
+                    None => break,
+                }
+            }
+            // Waiting for the ack was cancelled while the radio was in rx mode
+            rfm.abort().await.map_err(TxError::Rfm69Error)?;
+            timer.delay_us(config.jittered_retry_delay_us(!seed)).await
+        }
+        Err(TxError::AckTimeout)
+    }
+}
+
+/// Waits for the ack from `dst` that confirms the packet with sequence number `seq`, or another
+/// frame for `src`
+///
+/// Returns `None` if the ack was received, otherwise the other frame. Frames for other nodes and
+/// acks for earlier packets are discarded.
+pub async fn wait_for_mac_ack<SPI, RESET, DIO0, DELAY, CODEC, E>(
+    rfm: &mut Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    src: Address,
+    dst: Address,
+    seq: u8,
+) -> Result<Option<Packet>, Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    CODEC: FrameCodec<Frame = Packet>,
+{
+    loop {
+        // expect an ack from dst
+        let rx_packet = rfm.recv().await?;
+        if rx_packet.src == dst && rx_packet.dst == src && rx_packet.is_ack() {
+            // Acks without sequence number confirm any packet
+            if rx_packet.seq.unwrap_or(seq) == seq {
+                log::info!("Received valid ACK");
+                return Ok(None);
+            }
+            log::info!("Received ACK for an earlier packet");
+            continue;
+        }
+        if rx_packet.dst == src || rx_packet.dst == Address::Broadcast {
+            log::info!("Received other packet while waiting for ACK");
+            return Ok(Some(rx_packet));
+        }
+    }
+}
+
+/// Receives the next packet for `dst` or broadcast and sends the ack, if one is requested
+///
+/// Packets queued in `state` are returned first. Retransmissions of the last packet of a source are
+/// acknowledged again, but not returned. This function is cancel safe, see `Rfm69::recv`.
+pub async fn receive_packet<SPI, RESET, DIO0, DELAY, TIMER, CODEC, E>(
+    rfm: &mut Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    timer: &mut TIMER,
+    config: &MacConfig,
+    state: &mut MacState,
+    dst: Address,
+) -> Result<Packet, Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    TIMER: MacTimer,
+    CODEC: FrameCodec<Frame = Packet>,
+{
+    if let Some(packet) = state.queue().pop() {
+        return Ok(packet);
+    }
+    loop {
+        let packet = rfm.recv().await?;
+        if packet.dst == dst || packet.dst == Address::Broadcast {
+            if let Some(packet) = accept_packet(rfm, timer, config, state, dst, packet).await? {
+                return Ok(packet);
+            }
+        }
+    }
+}
+
+/// Sends the requested ack and returns the packet, unless it is a retransmission
+async fn accept_packet<SPI, RESET, DIO0, DELAY, TIMER, CODEC, E>(
+    rfm: &mut Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    timer: &mut TIMER,
+    config: &MacConfig,
+    state: &mut MacState,
+    own: Address,
+    packet: Packet,
+) -> Result<Option<Packet>, Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    TIMER: MacTimer,
+    CODEC: FrameCodec<Frame = Packet>,
+{
+    send_requested_ack(rfm, timer, config, own, &packet).await?;
+    // Only packets that request an ack are retransmitted
+    if packet.flags.ack_requested() && state.is_duplicate(&packet, timer.now_us()) {
+        log::info!("Dropping retransmitted packet {:?}", packet.seq);
+        return Ok(None);
+    }
+    Ok(Some(packet))
+}
+
+/// Sends the ack, if the packet was sent to `own` and requests one
+async fn send_requested_ack<SPI, RESET, DIO0, DELAY, TIMER, CODEC, E>(
+    rfm: &mut Rfm69<SPI, RESET, DIO0, DELAY, CODEC>,
+    timer: &mut TIMER,
+    config: &MacConfig,
+    own: Address,
+    packet: &Packet,
+) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    TIMER: MacTimer,
+    CODEC: FrameCodec<Frame = Packet>,
+{
+    // Broadcasts are never acknowledged
+    if packet.dst != own || own == Address::Broadcast {
+        return Ok(());
+    }
+    if packet.flags.ack_requested() {
+        let mut ack = Packet::new(own, packet.src, Flags::new().with_ack_response(true), &[])
+            .map_err(|_| Error::WrongPacketFormat)?;
+        ack.seq = packet.seq;
+        log::info!("Sending requested ACK as reply");
+
+        // Add small delay, if the sender is not able to switch into receive mode quick enough
+        timer.delay_us(config.ack_tx_delay_us).await;
+
+        rfm.send(&ack).await?;
+    }
+    Ok(())
+}
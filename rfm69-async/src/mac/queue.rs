@@ -0,0 +1,50 @@
+//! Frames received while the mac layer waits for an ack
+
+use heapless::Deque;
+
+use crate::Packet;
+
+/// Frames the queue holds, further frames are dropped
+const RX_QUEUE_LENGTH: usize = 4;
+
+/// Bounded queue of frames, which arrived while `send_packet` waited for an ack
+///
+/// The frames are returned by the next calls of `receive_packet` before any new frame. Requested
+/// acks are sent already, when a frame is queued.
+pub struct RxQueue {
+    packets: Deque<Packet, RX_QUEUE_LENGTH>,
+    dropped: u32,
+}
+
+impl RxQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            packets: Deque::new(),
+            dropped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Returns the amount of frames that were dropped, because the queue was full
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    pub(crate) fn push(&mut self, packet: Packet) {
+        if self.packets.push_back(packet).is_err() {
+            log::warn!("Receive queue is full, dropping packet");
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Packet> {
+        self.packets.pop_front()
+    }
+}
@@ -0,0 +1,112 @@
+//! State of the mac layer, that persists between packets
+
+use heapless::Vec;
+
+use super::RxQueue;
+use crate::{Address, Packet};
+
+/// Sources, whose last sequence number is remembered
+const DUPLICATE_CACHE_LENGTH: usize = 8;
+
+/// Time after which a sequence number is forgotten
+///
+/// All attempts of a packet end long before with the default timing. A packet with the same
+/// sequence number after that time is a new one, e.g. after the sender restarted or its sequence
+/// numbers wrapped around.
+const DUPLICATE_TIMEOUT_US: u64 = 10_000_000;
+
+/// Last sequence number per source, to detect retransmissions
+///
+/// If the cache is full, the source that was seen least recently is replaced.
+struct DuplicateCache {
+    /// (source, sequence number, time it was last seen), the most recent entry is last
+    entries: Vec<(u16, u8, u64), DUPLICATE_CACHE_LENGTH>,
+}
+
+impl DuplicateCache {
+    const fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Remembers the sequence number and returns if it was already the last one of that source
+    /// within `DUPLICATE_TIMEOUT_US`
+    fn check(&mut self, src: u16, seq: u8, now_us: u64) -> bool {
+        let duplicate = match self.entries.iter().position(|(s, _, _)| *s == src) {
+            Some(index) => {
+                let (_, last, seen_us) = self.entries.remove(index);
+                last == seq && now_us.saturating_sub(seen_us) < DUPLICATE_TIMEOUT_US
+            }
+            None => {
+                if self.entries.is_full() {
+                    self.entries.remove(0);
+                }
+                false
+            }
+        };
+        // Cannot fail, an entry was removed above if the cache was full
+        let _ = self.entries.push((src, seq, now_us));
+        duplicate
+    }
+}
+
+/// State of the mac layer of one node: sequence numbers, duplicate detection and received frames
+///
+/// `send_packet` and `receive_packet` must be called with the same state. `Mac` owns one.
+pub struct MacState {
+    queue: RxQueue,
+    seq: u8,
+    duplicates: DuplicateCache,
+    duplicates_dropped: u32,
+}
+
+impl MacState {
+    /// Returns the state of a node, whose first packet gets `initial_seq + 1`
+    ///
+    /// The initial sequence number should differ between restarts of the node, e.g. be taken from a
+    /// random number generator. Otherwise the first packet after a restart could be dropped as a
+    /// retransmission by receivers that still remember the last one before it.
+    pub fn new(initial_seq: u8) -> Self {
+        Self {
+            queue: RxQueue::new(),
+            seq: initial_seq,
+            duplicates: DuplicateCache::new(),
+            duplicates_dropped: 0,
+        }
+    }
+
+    /// Returns the frames that arrived while waiting for an ack and are not received yet
+    pub fn rx_queue(&self) -> &RxQueue {
+        &self.queue
+    }
+
+    /// Returns the amount of retransmitted frames, that were not delivered again
+    pub fn duplicates(&self) -> u32 {
+        self.duplicates_dropped
+    }
+
+    pub(crate) fn queue(&mut self) -> &mut RxQueue {
+        &mut self.queue
+    }
+
+    /// Returns the sequence number for the next packet
+    ///
+    /// Every packet uses up a sequence number, only its retransmissions repeat it.
+    pub(crate) fn next_seq(&mut self) -> u8 {
+        self.seq = self.seq.wrapping_add(1);
+        self.seq
+    }
+
+    /// Returns if the packet, received at `now_us`, is a retransmission of the last packet of its
+    /// source
+    pub(crate) fn is_duplicate(&mut self, packet: &Packet, now_us: u64) -> bool {
+        // Without sequence numbers retransmissions cannot be detected
+        let (Address::Unicast(src), Some(seq)) = (packet.src, packet.seq) else {
+            return false;
+        };
+        let duplicate = self.duplicates.check(src, seq, now_us);
+        if duplicate {
+            self.duplicates_dropped = self.duplicates_dropped.wrapping_add(1);
+        }
+        duplicate
+    }
+}
@@ -0,0 +1,113 @@
+//! Time source of the mac layer
+
+use core::future::Future;
+
+#[cfg(feature = "embassy")]
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_async::delay::DelayUs;
+
+use crate::rfm::{select, Either};
+
+/// Longest delay, before the clock is checked again while waiting for a deadline
+const CLOCK_POLL_US: u32 = 1_000;
+
+/// Delays and timeouts the mac layer needs
+pub trait MacTimer {
+    /// Returns a monotonic time in microseconds
+    fn now_us(&mut self) -> u64;
+
+    /// Waits for `us` microseconds
+    async fn delay_us(&mut self, us: u32);
+
+    /// Waits for the future, at most for `us` microseconds
+    ///
+    /// Returns `None` if the timeout elapsed first. The future is dropped in that case.
+    async fn with_timeout<F: Future>(&mut self, us: u32, future: F) -> Option<F::Output>;
+}
+
+/// Monotonic clock that is polled, e.g. a free running hardware timer
+pub trait Clock {
+    /// Returns a monotonic time in microseconds
+    fn now_us(&self) -> u64;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now_us(&self) -> u64 {
+        C::now_us(self)
+    }
+}
+
+/// Mac timer based on the embassy time driver
+#[cfg(feature = "embassy")]
+#[derive(Clone, Copy, Default)]
+pub struct EmbassyTimer;
+
+#[cfg(feature = "embassy")]
+impl MacTimer for EmbassyTimer {
+    fn now_us(&mut self) -> u64 {
+        Instant::now().as_micros()
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        Timer::after(Duration::from_micros(us as u64)).await
+    }
+
+    async fn with_timeout<F: Future>(&mut self, us: u32, future: F) -> Option<F::Output> {
+        embassy_time::with_timeout(Duration::from_micros(us as u64), future)
+            .await
+            .ok()
+    }
+}
+
+/// Mac timer based on a delay implementation and a polled clock
+///
+/// Works with any executor, e.g. RTIC, and with the simulation (`sim::Air` is a clock). Timeouts
+/// are measured with the clock, so they are accurate even if the delays take longer than requested.
+pub struct DelayTimer<DELAY, CLOCK> {
+    delay: DELAY,
+    clock: CLOCK,
+}
+
+impl<DELAY, CLOCK> DelayTimer<DELAY, CLOCK>
+where
+    DELAY: DelayUs,
+    CLOCK: Clock,
+{
+    pub fn new(delay: DELAY, clock: CLOCK) -> Self {
+        Self { delay, clock }
+    }
+}
+
+/// Waits until the clock reached the deadline
+async fn wait_until<DELAY: DelayUs, CLOCK: Clock>(delay: &mut DELAY, clock: &CLOCK, deadline: u64) {
+    loop {
+        let now = clock.now_us();
+        if now >= deadline {
+            return;
+        }
+        delay.delay_us((deadline - now).min(CLOCK_POLL_US as u64) as u32).await;
+    }
+}
+
+impl<DELAY, CLOCK> MacTimer for DelayTimer<DELAY, CLOCK>
+where
+    DELAY: DelayUs,
+    CLOCK: Clock,
+{
+    fn now_us(&mut self) -> u64 {
+        self.clock.now_us()
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        let deadline = self.clock.now_us() + us as u64;
+        wait_until(&mut self.delay, &self.clock, deadline).await
+    }
+
+    async fn with_timeout<F: Future>(&mut self, us: u32, future: F) -> Option<F::Output> {
+        let deadline = self.clock.now_us() + us as u64;
+        match select(future, wait_until(&mut self.delay, &self.clock, deadline)).await {
+            Either::First(output) => Some(output),
+            Either::Second(()) => None,
+        }
+    }
+}
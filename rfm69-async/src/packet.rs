@@ -7,6 +7,8 @@ use crate::flags::Flags;
 pub enum PacketError {
     DataTooLong,
     DataTooShort,
+    /// Address cannot be represented in the frame format
+    Address,
 }
 
 /// Packet that can be sent and received
@@ -16,15 +18,24 @@ pub struct Packet {
     pub src: Address,
     pub dst: Address,
     pub flags: Flags,
-    pub data: Vec<u8, 61>,
+    /// Sequence number of the sender, an ack carries the one of the packet it confirms
+    ///
+    /// `None` if the frame format has no sequence numbers.
+    pub seq: Option<u8>,
+    pub data: Vec<u8, 251>,
     pub rssi: Option<i16>,
+    /// Frequency correction in Hz the afc applied while receiving
+    pub afc: Option<i32>,
+    /// Frequency error in Hz of the last fei measurement
+    pub fei: Option<i32>,
 }
 
 impl Packet {
     /// this is without the length byte itself
-    const MIN_VALID_PACKET_LEN: u8 = 3;
+    const MIN_VALID_PACKET_LEN: u8 = 4;
 
-    const MAX_PAYLOAD_DATA_LENGTH: usize = 61;
+    /// Largest variable packet length (255) minus the header
+    const MAX_PAYLOAD_DATA_LENGTH: usize = 251;
 
     pub fn new(src: Address, dst: Address, flags: Flags, data: &[u8]) -> Result<Packet, PacketError> {
         if data.len() > Self::MAX_PAYLOAD_DATA_LENGTH {
@@ -34,8 +45,11 @@ impl Packet {
             src,
             dst,
             flags,
+            seq: None,
             data: Vec::from_slice(data).unwrap(),
             rssi: None,
+            afc: None,
+            fei: None,
         })
     }
 
@@ -44,36 +58,40 @@ impl Packet {
             return Err(PacketError::DataTooShort);
         }
         Ok(Self {
-            src: Address::from_u8(raw[0]),
-            dst: Address::from_u8(raw[1]),
+            dst: Address::from_u8(raw[0]),
+            src: Address::from_u8(raw[1]),
             flags: Flags::from_u8(raw[2]),
-            data: Vec::from_slice(&raw[3..len as usize]).unwrap(),
+            seq: Some(raw[3]),
+            data: Vec::from_slice(&raw[4..len as usize]).unwrap(),
             rssi: Some(rssi),
+            afc: None,
+            fei: None,
         })
     }
 
-    /// Converts packet to byte slice for the fifo
+    /// Converts packet to byte slice for the fifo, without the length byte
     ///
     /// The returned length is the amount of bytes written into the given
     /// array.
     /// # Arguments
     /// * `raw` - This array is filled
-    pub(crate) fn to_slice(&self, raw: &mut [u8; 65]) -> Result<u8, PacketError> {
-        // Length of the data inside the fifo (excluding the length itself)
-        let fifo_len = self.data.len() as u8 + Self::MIN_VALID_PACKET_LEN;
-
-        raw[0] = fifo_len;
-        raw[1] = self.src.as_u8();
-        raw[2] = self.dst.as_u8();
-        raw[3] = self.flags.as_u8();
+    pub(crate) fn to_slice(&self, raw: &mut [u8; 255]) -> Result<usize, PacketError> {
+        // The destination is first, so the radio can filter the address
+        raw[0] = self.dst.as_u8().ok_or(PacketError::Address)?;
+        raw[1] = self.src.as_u8().ok_or(PacketError::Address)?;
+        raw[2] = self.flags.as_u8();
+        raw[3] = self.seq.unwrap_or(0);
         raw[4..4 + self.data.len()].copy_from_slice(self.data.as_slice());
-        Ok(fifo_len + 1)
+        Ok(self.data.len() + Self::MIN_VALID_PACKET_LEN as usize)
+    }
+
+    /// Sets the sequence number
+    pub fn with_seq(mut self, seq: u8) -> Self {
+        self.seq = Some(seq);
+        self
     }
 
     pub fn is_ack(&self) -> bool {
-        match self.flags {
-            Flags::None => false,
-            Flags::Ack(_) => true,
-        }
+        self.flags.is_ack()
     }
 }
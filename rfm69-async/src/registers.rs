@@ -16,9 +16,21 @@ pub enum Register {
     FrfMsb = 0x07,
     FrfMid = 0x08,
     FrfLsb = 0x09,
+    AfcCtrl = 0x0B,
+    Listen1 = 0x0D,
+    Listen2 = 0x0E,
+    Listen3 = 0x0F,
     Version = 0x10,
+    PaLevel = 0x11,
+    Ocp = 0x13,
     Lna = 0x18,
     RxBw = 0x19,
+    AfcBw = 0x1A,
+    AfcFei = 0x1E,
+    AfcMsb = 0x1F,
+    AfcLsb = 0x20,
+    FeiMsb = 0x21,
+    FeiLsb = 0x22,
     RssiValue = 0x24,
     DioMapping1 = 0x25,
     DioMapping2 = 0x26,
@@ -30,9 +42,15 @@ pub enum Register {
     SyncConfig = 0x2E,
     SyncValue1 = 0x2F,
     PacketConfig1 = 0x37,
+    NodeAdrs = 0x39,
+    BroadcastAdrs = 0x3A,
     FifoThresh = 0x3C,
     PacketConfig2 = 0x3D,
+    AesKey1 = 0x3E,
+    TestPa1 = 0x5A,
+    TestPa2 = 0x5C,
     TestDagc = 0x6F,
+    TestAfc = 0x71,
 }
 
 impl Register {
@@ -60,6 +78,7 @@ impl OpMode {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub struct Modulation {
     pub data_mode: DataMode,
     pub modulation_type: ModulationType,
@@ -70,22 +89,47 @@ impl Modulation {
     pub(crate) fn value(&self) -> u8 {
         self.data_mode as u8 | self.modulation_type as u8 | self.shaping as u8
     }
+
+    pub(crate) fn from_value(value: u8) -> Option<Self> {
+        let data_mode = match value & 0x60 {
+            0x00 => DataMode::Packet,
+            0x40 => DataMode::Continuous,
+            0x60 => DataMode::ContinuousBitSync,
+            _ => return None,
+        };
+        let modulation_type = match value & 0x18 {
+            0x00 => ModulationType::Fsk,
+            0x08 => ModulationType::Ook,
+            _ => return None,
+        };
+        let shaping = match value & 0x03 {
+            0x00 => ModulationShaping::Shaping00,
+            0x01 => ModulationShaping::Shaping01,
+            0x02 => ModulationShaping::Shaping10,
+            _ => ModulationShaping::Shaping11,
+        };
+        Some(Self {
+            data_mode,
+            modulation_type,
+            shaping,
+        })
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum DataMode {
     Packet = 0x00,
     Continuous = 0x40,
     ContinuousBitSync = 0x60,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum ModulationType {
     Fsk = 0x00,
     Ook = 0x08,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum ModulationShaping {
     Shaping00 = 0x00,
     Shaping01 = 0x01,
@@ -93,11 +137,23 @@ pub enum ModulationShaping {
     Shaping11 = 0x03,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum FifoMode {
     NotEmpty,
     Level(u8),
 }
 
+impl FifoMode {
+    pub(crate) fn from_value(value: u8) -> Self {
+        if value & 0x80 != 0 {
+            Self::NotEmpty
+        } else {
+            Self::Level(value & 0x7f)
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
 pub enum InterPacketRxDelay {
     Delay1Bit = 0x00,
     Delay2Bits = 0x10,
@@ -113,23 +169,27 @@ pub enum InterPacketRxDelay {
     Delay2048Bits = 0xB0,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum PacketDc {
     None = 0x00,
     Manchester = 0x20,
     Whitening = 0x40,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum PacketFiltering {
     None = 0x00,
     Address = 0x02,
     Broadcast = 0x04,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum PacketFormat {
     Variable(u8),
     Fixed(u8),
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub struct PacketConfig {
     pub format: PacketFormat,
     pub dc: PacketDc,
@@ -139,16 +199,247 @@ pub struct PacketConfig {
     pub auto_rx_restart: bool,
 }
 
+impl PacketConfig {
+    /// Decodes the registers PacketConfig1, PayloadLength and PacketConfig2
+    pub(crate) fn from_values(config1: u8, length: u8, config2: u8) -> Option<Self> {
+        let format = if config1 & 0x80 != 0 {
+            PacketFormat::Variable(length)
+        } else {
+            PacketFormat::Fixed(length)
+        };
+        let dc = match config1 & 0x60 {
+            0x00 => PacketDc::None,
+            0x20 => PacketDc::Manchester,
+            0x40 => PacketDc::Whitening,
+            _ => return None,
+        };
+        let filtering = match config1 & 0x06 {
+            0x00 => PacketFiltering::None,
+            0x02 => PacketFiltering::Address,
+            0x04 => PacketFiltering::Broadcast,
+            _ => return None,
+        };
+        let interpacket_rx_delay = match config2 & 0xf0 {
+            0x00 => InterPacketRxDelay::Delay1Bit,
+            0x10 => InterPacketRxDelay::Delay2Bits,
+            0x20 => InterPacketRxDelay::Delay4Bits,
+            0x30 => InterPacketRxDelay::Delay8Bits,
+            0x40 => InterPacketRxDelay::Delay16Bits,
+            0x50 => InterPacketRxDelay::Delay32Bits,
+            0x60 => InterPacketRxDelay::Delay64Bits,
+            0x70 => InterPacketRxDelay::Delay128Bits,
+            0x80 => InterPacketRxDelay::Delay256Bits,
+            0x90 => InterPacketRxDelay::Delay512Bits,
+            0xA0 => InterPacketRxDelay::Delay1024Bits,
+            0xB0 => InterPacketRxDelay::Delay2048Bits,
+            _ => return None,
+        };
+        Some(Self {
+            format,
+            dc,
+            crc: config1 & 0x10 != 0,
+            filtering,
+            interpacket_rx_delay,
+            auto_rx_restart: config2 & 0x02 != 0,
+        })
+    }
+}
+
+/// Listen mode configuration
+///
+/// The radio alternates between idle and rx. Each phase lasts `coefficient * resolution`.
+pub struct ListenConfig {
+    pub idle_resolution: ListenResolution,
+    pub idle_coefficient: u8,
+    pub rx_resolution: ListenResolution,
+    pub rx_coefficient: u8,
+    pub criteria: ListenCriteria,
+    pub end: ListenEnd,
+}
+
+impl ListenConfig {
+    pub(crate) fn value(&self) -> u8 {
+        (self.idle_resolution as u8) << 6 | (self.rx_resolution as u8) << 4 | self.criteria as u8 | self.end as u8
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum ListenResolution {
+    Us64 = 0b01,
+    Ms4dot1 = 0b10,
+    Ms262 = 0b11,
+}
+
+/// Condition to accept a packet during the rx phase of listen mode
+#[derive(Copy, Clone)]
+pub enum ListenCriteria {
+    /// Signal strength is above the rssi threshold
+    Rssi = 0x00,
+    /// Signal strength is above the rssi threshold and sync address matched
+    RssiSyncAddress = 0x08,
+}
+
+/// Action after a packet was accepted in listen mode
+#[derive(Copy, Clone)]
+pub enum ListenEnd {
+    /// Stay in rx mode, listen mode must be stopped
+    Rx = 0x00,
+    /// Stay in rx mode until PayloadReady or Timeout, then go to the configured mode. Listen mode
+    /// must be stopped
+    RxUntilPayloadOrTimeout = 0x02,
+    /// Stay in rx mode until PayloadReady or Timeout, then resume listen mode. Fifo content is lost
+    /// at the next rx phase
+    Resume = 0x04,
+}
+
+/// Variant of the rfm69 module
+#[derive(Copy, Clone, PartialEq)]
+pub enum Variant {
+    /// RFM69W/CW, only PA0 is connected to the antenna
+    Rfm69W,
+    /// RFM69HW/HCW, only PA1 and PA2 are connected to the antenna
+    Rfm69Hw,
+}
+
+/// Offset of the crystal from its nominal frequency (32 MHz)
+#[derive(Copy, Clone)]
+pub enum CrystalOffset {
+    Hz(i32),
+    Ppm(i32),
+}
+
+/// Upper bounds for waiting on the radio, in microseconds
+///
+/// The waited time is measured with the delay implementation. Time spent on the spi bus is not
+/// counted, so the actual deadline may be slightly longer.
+#[derive(Copy, Clone)]
+pub struct Timeouts {
+    /// Mode switch, until ModeReady is signalled
+    pub mode_ready_us: u32,
+    /// Transmission of a packet, until PacketSent is signalled
+    pub packet_sent_us: u32,
+    /// Fifo level crossing the threshold while a large packet is streamed
+    pub fifo_level_us: u32,
+    /// Frequency error measurement, until FeiDone is signalled
+    pub fei_us: u32,
+    /// Reception of a packet, `None` to wait until a packet arrives
+    pub rx_us: Option<u32>,
+}
+
+impl Default for Timeouts {
+    /// Timeouts that fit the largest packets at the lowest bitrate (1.2 kBit/sec)
+    fn default() -> Self {
+        Self {
+            mode_ready_us: 10_000,
+            packet_sent_us: 2_500_000,
+            fifo_level_us: 500_000,
+            fei_us: 10_000,
+            rx_us: None,
+        }
+    }
+}
+
+/// Output power in dBm and the power amplifiers used for it
+#[derive(Copy, Clone, PartialEq)]
+pub enum OutputPower {
+    /// PA0, -18 to +13 dBm (RFM69W)
+    Pa0(i8),
+    /// PA1, -2 to +13 dBm (RFM69HW)
+    Pa1(i8),
+    /// PA1 and PA2, +2 to +17 dBm (RFM69HW)
+    Pa1Pa2(i8),
+    /// PA1 and PA2 with high power settings, +5 to +20 dBm (RFM69HW)
+    Pa1Pa2Boost(i8),
+}
+
+impl OutputPower {
+    /// Returns the output power with the power amplifiers the variant needs for it
+    pub fn from_dbm(variant: Variant, dbm: i8) -> Self {
+        match variant {
+            Variant::Rfm69W => Self::Pa0(dbm),
+            Variant::Rfm69Hw if dbm <= 13 => Self::Pa1(dbm),
+            Variant::Rfm69Hw if dbm <= 17 => Self::Pa1Pa2(dbm),
+            Variant::Rfm69Hw => Self::Pa1Pa2Boost(dbm),
+        }
+    }
+
+    /// Returns the content of the PaLevel register, or `None` if the power is out of range
+    pub(crate) fn value(&self) -> Option<u8> {
+        let (pa, dbm, range, offset) = match *self {
+            Self::Pa0(dbm) => (0x80, dbm, -18..=13, 18),
+            Self::Pa1(dbm) => (0x40, dbm, -2..=13, 18),
+            Self::Pa1Pa2(dbm) => (0x60, dbm, 2..=17, 14),
+            Self::Pa1Pa2Boost(dbm) => (0x60, dbm, 5..=20, 11),
+        };
+        range.contains(&dbm).then(|| pa | (dbm + offset) as u8)
+    }
+
+    /// Decodes the PaLevel register, `high_power` tells if the high power settings are enabled
+    pub(crate) fn from_value(value: u8, high_power: bool) -> Option<Self> {
+        let level = (value & 0x1f) as i8;
+        match value & 0xe0 {
+            0x80 => Some(Self::Pa0(level - 18)),
+            0x40 => Some(Self::Pa1(level - 18)),
+            0x60 if high_power => Some(Self::Pa1Pa2Boost(level - 11)),
+            0x60 => Some(Self::Pa1Pa2(level - 14)),
+            _ => None,
+        }
+    }
+
+    /// Returns the output power in dBm
+    pub fn dbm(&self) -> i8 {
+        match *self {
+            Self::Pa0(dbm) | Self::Pa1(dbm) | Self::Pa1Pa2(dbm) | Self::Pa1Pa2Boost(dbm) => dbm,
+        }
+    }
+
+    /// Returns true, if the power amplifier is usable with the variant
+    pub(crate) fn is_available(&self, variant: Variant) -> bool {
+        matches!(
+            (self, variant),
+            (Self::Pa0(_), Variant::Rfm69W) | (Self::Pa1(_) | Self::Pa1Pa2(_) | Self::Pa1Pa2Boost(_), Variant::Rfm69Hw)
+        )
+    }
+
+    pub(crate) fn is_high_power(&self) -> bool {
+        matches!(self, Self::Pa1Pa2Boost(_))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
 pub struct LnaConfig {
     pub zin: LnaImpedance,
     pub gain_select: LnaGain,
 }
 
+impl LnaConfig {
+    pub(crate) fn from_value(value: u8) -> Option<Self> {
+        let zin = if value & 0x80 != 0 {
+            LnaImpedance::Ohm200
+        } else {
+            LnaImpedance::Ohm50
+        };
+        let gain_select = match value & 0x07 {
+            0b000 => LnaGain::AgcLoop,
+            0b001 => LnaGain::G1,
+            0b010 => LnaGain::G2,
+            0b011 => LnaGain::G3,
+            0b100 => LnaGain::G4,
+            0b101 => LnaGain::G5,
+            0b110 => LnaGain::G6,
+            _ => return None,
+        };
+        Some(Self { zin, gain_select })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
 pub enum LnaImpedance {
     Ohm50 = 0x00,
     Ohm200 = 0x80,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum LnaGain {
     AgcLoop = 0b000,
     G1 = 0b001,
@@ -159,12 +450,25 @@ pub enum LnaGain {
     G6 = 0b110,
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum ContinuousDagc {
     Normal = 0x00,
     ImprovedMarginAfcLowBetaOn1 = 0x20,
     ImprovedMarginAfcLowBetaOn0 = 0x30,
 }
 
+impl ContinuousDagc {
+    pub(crate) fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Normal),
+            0x20 => Some(Self::ImprovedMarginAfcLowBetaOn1),
+            0x30 => Some(Self::ImprovedMarginAfcLowBetaOn0),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
 pub struct RxBw<T>
 where
     T: RxBwFreq,
@@ -173,6 +477,62 @@ where
     pub rx_bw: T,
 }
 
+impl<T> RxBw<T>
+where
+    T: RxBwFreq,
+{
+    pub(crate) fn from_value(value: u8, modulation_type: ModulationType) -> Option<Self> {
+        let dcc_cutoff = match value & 0xe0 {
+            0x00 => DccCutoff::Percent16,
+            0x20 => DccCutoff::Percent8,
+            0x40 => DccCutoff::Percent4,
+            0x60 => DccCutoff::Percent2,
+            0x80 => DccCutoff::Percent1,
+            0xA0 => DccCutoff::Percent0dot5,
+            0xC0 => DccCutoff::Percent0dot25,
+            _ => DccCutoff::Percent0dot125,
+        };
+        Some(Self {
+            dcc_cutoff,
+            rx_bw: T::from_value(value & 0x1f, modulation_type)?,
+        })
+    }
+}
+
+/// Automatic frequency correction configuration
+pub struct AfcConfig<T>
+where
+    T: RxBwFreq,
+{
+    /// Afc is performed each time rx mode is entered
+    pub auto_on: bool,
+    /// The afc register is cleared before a new afc phase
+    pub auto_clear: bool,
+    /// Frequency offset in 488 Hz steps for systems with a low modulation index, `None` to disable
+    pub low_beta_offset: Option<i8>,
+    /// Rx bandwidth used during afc
+    pub bandwidth: RxBw<T>,
+}
+
+#[repr(u8)]
+pub enum AfcFei {
+    AfcStart = 0x01,
+    AfcClear = 0x02,
+    AfcAutoOn = 0x04,
+    AfcAutoclearOn = 0x08,
+    AfcDone = 0x10,
+    FeiStart = 0x20,
+    FeiDone = 0x40,
+}
+
+impl core::ops::BitAnd<AfcFei> for u8 {
+    type Output = Self;
+    fn bitand(self, rhs: AfcFei) -> Self::Output {
+        self & rhs as Self
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
 pub enum DccCutoff {
     Percent16 = 0x00,
     Percent8 = 0x20,
@@ -186,8 +546,63 @@ pub enum DccCutoff {
 
 pub trait RxBwFreq {
     fn value(&self) -> u8;
+
+    /// Returns the bandwidth in Hz
+    fn hz(&self) -> u32;
+
+    /// Returns the bandwidth for the mantissa and exponent bits of the register
+    ///
+    /// The bandwidth depends on the modulation type, `None` if the type of the bandwidth does not
+    /// belong to it.
+    fn from_value(value: u8, modulation_type: ModulationType) -> Option<Self>
+    where
+        Self: Sized;
+}
+
+/// Returns the rx bandwidth in Hz for the register value, `exp_offset` is 2 for fsk and 3 for ook
+fn rx_bw_hz(value: u8, exp_offset: u8) -> u32 {
+    let mant = match (value >> 3) & 0x03 {
+        0b00 => 16,
+        0b01 => 20,
+        _ => 24,
+    };
+    32_000_000 / (mant << ((value & 0x07) + exp_offset))
+}
+
+/// Rx bandwidth of either modulation type, as register value
+///
+/// Used to store a configuration that was validated already. The modulation type is kept, because
+/// the bandwidth in Hz of a register value depends on it.
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) struct RxBwValue {
+    value: u8,
+    modulation_type: ModulationType,
+}
+
+impl RxBwValue {
+    pub(crate) fn new(value: u8, modulation_type: ModulationType) -> Self {
+        Self { value, modulation_type }
+    }
+}
+
+impl RxBwFreq for RxBwValue {
+    fn value(&self) -> u8 {
+        self.value
+    }
+
+    fn hz(&self) -> u32 {
+        match self.modulation_type {
+            ModulationType::Fsk => rx_bw_hz(self.value, 2),
+            ModulationType::Ook => rx_bw_hz(self.value, 3),
+        }
+    }
+
+    fn from_value(value: u8, modulation_type: ModulationType) -> Option<Self> {
+        Some(Self::new(value, modulation_type))
+    }
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum RxBwFsk {
     Khz2dot6,
     Khz3dot1,
@@ -245,8 +660,47 @@ impl RxBwFreq for RxBwFsk {
             RxBwFsk::Khz500dot0 => 0,
         }
     }
+
+    fn hz(&self) -> u32 {
+        rx_bw_hz(self.value(), 2)
+    }
+
+    fn from_value(value: u8, modulation_type: ModulationType) -> Option<Self> {
+        if modulation_type != ModulationType::Fsk {
+            return None;
+        }
+        [
+            RxBwFsk::Khz2dot6,
+            RxBwFsk::Khz3dot1,
+            RxBwFsk::Khz3dot9,
+            RxBwFsk::Khz5dot2,
+            RxBwFsk::Khz6dot3,
+            RxBwFsk::Khz7dot8,
+            RxBwFsk::Khz10dot4,
+            RxBwFsk::Khz12dot5,
+            RxBwFsk::Khz15dot6,
+            RxBwFsk::Khz20dot8,
+            RxBwFsk::Khz25dot0,
+            RxBwFsk::Khz31dot3,
+            RxBwFsk::Khz41dot7,
+            RxBwFsk::Khz50dot0,
+            RxBwFsk::Khz62dot5,
+            RxBwFsk::Khz83dot3,
+            RxBwFsk::Khz100dot0,
+            RxBwFsk::Khz125dot0,
+            RxBwFsk::Khz166dot7,
+            RxBwFsk::Khz200dot0,
+            RxBwFsk::Khz250dot0,
+            RxBwFsk::Khz333dot3,
+            RxBwFsk::Khz400dot0,
+            RxBwFsk::Khz500dot0,
+        ]
+        .into_iter()
+        .find(|bw| bw.value() == value)
+    }
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum RxBwOok {
     Khz1dot3,
     Khz1dot6,
@@ -304,6 +758,44 @@ impl RxBwFreq for RxBwOok {
             RxBwOok::Khz250dot0 => 0,
         }
     }
+
+    fn hz(&self) -> u32 {
+        rx_bw_hz(self.value(), 3)
+    }
+
+    fn from_value(value: u8, modulation_type: ModulationType) -> Option<Self> {
+        if modulation_type != ModulationType::Ook {
+            return None;
+        }
+        [
+            RxBwOok::Khz1dot3,
+            RxBwOok::Khz1dot6,
+            RxBwOok::Khz2dot0,
+            RxBwOok::Khz2dot6,
+            RxBwOok::Khz3dot1,
+            RxBwOok::Khz3dot9,
+            RxBwOok::Khz5dot2,
+            RxBwOok::Khz6dot3,
+            RxBwOok::Khz7dot8,
+            RxBwOok::Khz10dot4,
+            RxBwOok::Khz12dot5,
+            RxBwOok::Khz15dot6,
+            RxBwOok::Khz20dot8,
+            RxBwOok::Khz25dot0,
+            RxBwOok::Khz31dot3,
+            RxBwOok::Khz41dot7,
+            RxBwOok::Khz50dot0,
+            RxBwOok::Khz62dot5,
+            RxBwOok::Khz83dot3,
+            RxBwOok::Khz100dot0,
+            RxBwOok::Khz125dot0,
+            RxBwOok::Khz166dot7,
+            RxBwOok::Khz200dot0,
+            RxBwOok::Khz250dot0,
+        ]
+        .into_iter()
+        .find(|bw| bw.value() == value)
+    }
 }
 
 #[repr(u8)]
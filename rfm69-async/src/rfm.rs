@@ -1,30 +1,101 @@
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::task::Poll;
+
 use embedded_hal_1::digital::{InputPin, OutputPin};
 use embedded_hal_1::spi::Operation;
 use embedded_hal_async::delay::DelayUs;
 use embedded_hal_async::digital::Wait;
 use embedded_hal_async::spi::SpiDevice;
+use heapless::Vec;
 
-use crate::error::Error;
-use crate::packet::Packet;
+use crate::config::Rfm69Config;
+use crate::error::{Error, RecoveryCause, WaitState};
+use crate::frame::{FrameCodec, FrameFormat, RxInfo};
 use crate::registers::*;
 
 /// Expected content of Register::Version
 const VERSION_CHECK: u8 = 0x24;
 
+/// Size of the hardware fifo
+const FIFO_SIZE: usize = 66;
+
+/// Largest content of the fifo for one packet (length byte and 255 bytes)
+const MAX_FIFO_DATA: usize = 256;
+
+/// Content of TestPa1 and TestPa2 for normal and high power (+20 dBm) operation
+const TEST_PA_NORMAL: [u8; 2] = [0x55, 0x70];
+const TEST_PA_HIGH_POWER: [u8; 2] = [0x5d, 0x7c];
+
+/// Content of Register::Ocp with overcurrent protection on (95 mA) and off
+const OCP_ON: u8 = 0x1a;
+const OCP_OFF: u8 = 0x0f;
+
+/// Default amount of timeouts in a row, after which the radio is recovered
+const MAX_TIMEOUTS_IN_ROW: u8 = 3;
+
+/// Interval to poll the interrupt registers, if the dio signals are not connected
+const POLL_INTERVAL_US: u32 = 100;
+
+/// Largest packet if aes is enabled, without the length byte
+pub(crate) const MAX_AES_PACKET_LENGTH: usize = 64;
+
+/// Packet format of the radio after reset
+const RESET_PACKET_FORMAT: PacketFormat = PacketFormat::Fixed(64);
+
 // 1_000_000 larger for better precision.
 const F_SCALE: u64 = 1_000_000;
 const FOSC: u64 = 32_000_000 * F_SCALE;
-const FSTEP: u64 = FOSC / 524_288; // FOSC/2^19
+/// Largest value of the 14 bit frequency deviation register
+const FDEV_MAX_REG: u16 = 0x3fff;
 
 /// The rfm69 transceiver
-pub struct Rfm69<SPI, RESET, DIO0, DELAY> {
+///
+/// The frames of `send` and `recv` are converted by the codec, by default `FrameFormat` with `Packet`
+/// frames.
+pub struct Rfm69<SPI, RESET, DIO0, DELAY, CODEC = FrameFormat> {
     spi: SPI,
     reset: RESET,
     dio0: Option<DIO0>,
+    dio1: Option<DIO0>,
     delay: DELAY,
 
     /// Current cached active mode
     pub mode: OpMode,
+
+    /// Aes encryption is enabled
+    aes: bool,
+
+    /// Packet format last written with `packet`, the framing of `send` and `recv` depends on it
+    packet_format: PacketFormat,
+
+    variant: Variant,
+
+    /// Actual crystal frequency, F_SCALE larger for better precision
+    fosc: u64,
+
+    /// The high power settings must be enabled during tx
+    high_power: bool,
+
+    timeouts: Timeouts,
+
+    /// Last configuration written with `apply`, to restore it after a recovery
+    config: Option<Rfm69Config<RxBwValue>>,
+
+    /// Waits that timed out in a row
+    timeouts_in_row: u8,
+
+    /// Timeouts in a row, after which the radio is recovered
+    max_timeouts_in_row: u8,
+
+    /// Amount of recoveries since the instance was created
+    recoveries: u32,
+
+    /// A send or receive was cancelled, the radio may be in rx or tx mode with a partial fifo
+    interrupted: bool,
+
+    /// Converts the frames to and from the fifo content
+    codec: CODEC,
 }
 
 impl<SPI, RESET, DIO0, DELAY, E> Rfm69<SPI, RESET, DIO0, DELAY>
@@ -52,11 +123,133 @@ where
             spi,
             reset,
             dio0,
+            dio1: None,
             delay,
             mode: OpMode::Standby,
+            aes: false,
+            packet_format: RESET_PACKET_FORMAT,
+            variant: Variant::Rfm69W,
+            high_power: false,
+            fosc: FOSC,
+            timeouts: Timeouts::default(),
+            config: None,
+            timeouts_in_row: 0,
+            max_timeouts_in_row: MAX_TIMEOUTS_IN_ROW,
+            recoveries: 0,
+            interrupted: false,
+            codec: FrameFormat::Native,
+        }
+    }
+}
+
+impl<SPI, RESET, DIO0, DELAY, E, CODEC> Rfm69<SPI, RESET, DIO0, DELAY, CODEC>
+where
+    SPI: SpiDevice<u8, Error = E>,
+    RESET: OutputPin,
+    DIO0: InputPin + Wait,
+    DELAY: DelayUs,
+    CODEC: FrameCodec,
+{
+    /// Sets the offset of the crystal from its nominal 32 MHz
+    ///
+    /// Frequency, frequency deviation and bitrate are calculated with the corrected crystal
+    /// frequency, so they must be set afterwards.
+    pub fn with_crystal_offset(mut self, offset: CrystalOffset) -> Self {
+        self.fosc = match offset {
+            CrystalOffset::Hz(hz) => (FOSC as i64 + hz as i64 * F_SCALE as i64) as u64,
+            CrystalOffset::Ppm(ppm) => (FOSC as i64 + ppm as i64 * (FOSC / F_SCALE) as i64) as u64,
+        };
+        self
+    }
+
+    /// Frequency synthesizer step, F_SCALE larger for better precision
+    fn fstep(&self) -> u64 {
+        self.fosc / 524_288 // FOSC/2^19
+    }
+
+    fn bit_rate_reg(&self, bit_rate: u32) -> u16 {
+        (self.fosc / (bit_rate as u64 * F_SCALE)) as u16
+    }
+
+    fn bit_rate_from_reg(&self, reg: u16) -> u32 {
+        (self.fosc / (reg.max(1) as u64 * F_SCALE)) as u32
+    }
+
+    /// Register value for frequency and frequency deviation
+    fn fstep_reg(&self, hz: u32) -> u32 {
+        ((hz as u64 * F_SCALE) / self.fstep()) as u32
+    }
+
+    fn fstep_from_reg(&self, reg: u32) -> u32 {
+        (reg as u64 * self.fstep() / F_SCALE) as u32
+    }
+
+    /// Sets the variant of the module
+    ///
+    /// The variant determines the power amplifiers that can be used. Default is `Variant::Rfm69W`.
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Sets the upper bounds for waiting on the radio
+    ///
+    /// If a wait exceeds its bound, `Error::Timeout` is returned. Default is `Timeouts::default()`.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Sets the amount of timeouts in a row, after which `send` and `recv` recover the radio
+    ///
+    /// Default is 3. Timeouts while waiting for a packet to arrive are not counted.
+    pub fn with_recovery_after_timeouts(mut self, timeouts: u8) -> Self {
+        self.max_timeouts_in_row = timeouts.max(1);
+        self
+    }
+
+    /// Adds the dio1 signal
+    ///
+    /// Dio1 signals the fifo level, so packets larger than the fifo are sent and received without
+    /// polling the interrupt register. The pin must be of the same type as dio0, e.g. by degrading
+    /// both pins to a generic pin type. Its errors are reported as `Error::DIO1`, which therefore
+    /// has the error type of dio0.
+    pub fn with_dio1(mut self, dio1: DIO0) -> Self {
+        self.dio1 = Some(dio1);
+        self
+    }
+
+    /// Replaces the codec of the frames
+    ///
+    /// E.g. `FrameFormat::LowPowerLab` to talk to nodes using the LowPowerLab library, or `Raw` to
+    /// send and receive the fifo content unchanged.
+    pub fn with_codec<C: FrameCodec>(self, codec: C) -> Rfm69<SPI, RESET, DIO0, DELAY, C> {
+        Rfm69 {
+            spi: self.spi,
+            reset: self.reset,
+            dio0: self.dio0,
+            dio1: self.dio1,
+            delay: self.delay,
+            mode: self.mode,
+            aes: self.aes,
+            packet_format: self.packet_format,
+            variant: self.variant,
+            fosc: self.fosc,
+            high_power: self.high_power,
+            timeouts: self.timeouts,
+            config: self.config,
+            timeouts_in_row: self.timeouts_in_row,
+            max_timeouts_in_row: self.max_timeouts_in_row,
+            recoveries: self.recoveries,
+            interrupted: self.interrupted,
+            codec,
         }
     }
 
+    pub fn codec(&self) -> &CODEC {
+        &self.codec
+    }
+
     /// Resets the rfm69 transceiver
     ///
     /// The transceiver is reset using the pin. Afterwards the version register is read to ensure that the transceiver is usable.
@@ -69,6 +262,10 @@ where
         let version = self.read_register(Register::Version).await?;
         log::debug!("Version: {version:#x}");
         if version == VERSION_CHECK {
+            self.aes = false;
+            self.packet_format = RESET_PACKET_FORMAT;
+            self.high_power = false;
+            self.interrupted = false;
             self.set_mode(OpMode::Sleep).await?;
             Ok(())
         } else {
@@ -94,23 +291,35 @@ where
     /// Sets the data bitrate in corresponding registers
     ///
     /// There might be a loss of precision, so that the actual data rate is slightly off.
-    pub async fn bit_rate(&mut self, bit_rate: u32) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
-        let reg = (FOSC / (bit_rate as u64 * F_SCALE)) as u16;
-        self.write_registers(Register::BitrateMsb, &reg.to_be_bytes()).await
+    /// Returns the actual data rate.
+    pub async fn bit_rate(&mut self, bit_rate: u32) -> Result<u32, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.bit_rate_reg(bit_rate);
+        self.write_registers(Register::BitrateMsb, &reg.to_be_bytes()).await?;
+        Ok(self.bit_rate_from_reg(reg))
     }
 
     /// Sets the radio frequency in corresponding registers
     ///
     /// There might be a loss of precision, so that the actual frequency is slightly off.
-    pub async fn frequency(&mut self, frequency: u32) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
-        let reg = ((frequency as u64 * F_SCALE) / FSTEP) as u32;
-        self.write_registers(Register::FrfMsb, &reg.to_be_bytes()[1..]).await
+    /// Returns the actual frequency.
+    pub async fn frequency(&mut self, frequency: u32) -> Result<u32, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.fstep_reg(frequency);
+        self.write_registers(Register::FrfMsb, &reg.to_be_bytes()[1..]).await?;
+        Ok(self.fstep_from_reg(reg))
     }
 
     /// Sets the frequency deviation in corresponding registers
-    pub async fn fdev(&mut self, fdev: u32) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
-        let reg = ((fdev as u64 * F_SCALE) / FSTEP) as u16;
-        self.write_registers(Register::FdevMsb, &reg.to_be_bytes()).await
+    ///
+    /// There might be a loss of precision, so that the actual frequency deviation is slightly off.
+    /// Returns the actual frequency deviation, or `Error::FdevRange` if the deviation does not fit
+    /// into the 14 bit register.
+    pub async fn fdev(&mut self, fdev: u32) -> Result<u32, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = u16::try_from(self.fstep_reg(fdev))
+            .ok()
+            .filter(|reg| *reg <= FDEV_MAX_REG)
+            .ok_or(Error::FdevRange)?;
+        self.write_registers(Register::FdevMsb, &reg.to_be_bytes()).await?;
+        Ok(self.fstep_from_reg(reg as u32))
     }
 
     /// Sets the rx bandwidth in corresponding register
@@ -122,6 +331,50 @@ where
             .await
     }
 
+    /// Configure automatic frequency correction in corresponding registers
+    pub async fn afc<RxBwT>(&mut self, config: AfcConfig<RxBwT>) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq,
+    {
+        self.write_register(
+            Register::AfcBw,
+            config.bandwidth.dcc_cutoff as u8 | config.bandwidth.rx_bw.value(),
+        )
+        .await?;
+        let low_beta = match config.low_beta_offset {
+            Some(offset) => {
+                self.write_register(Register::TestAfc, offset as u8).await?;
+                0x20
+            }
+            None => 0x00,
+        };
+        self.update_register(Register::AfcCtrl, |r| (r & 0xdf) | low_beta)
+            .await?;
+        let reg = (config.auto_clear as u8) << 3 | (config.auto_on as u8) << 2;
+        self.update_register(Register::AfcFei, |r| (r & 0x03) | reg).await
+    }
+
+    /// Measures the frequency error of the received signal in Hz
+    ///
+    /// The radio must be in rx mode and receive a signal, e.g. the preamble.
+    pub async fn fei(&mut self) -> Result<i32, Error<E, RESET::Error, DIO0::Error>> {
+        self.update_register(Register::AfcFei, |r| (r & 0x0c) | AfcFei::FeiStart as u8)
+            .await?;
+        self.poll_register(Register::AfcFei, self.timeouts.fei_us, WaitState::FeiDone, |r| {
+            r & AfcFei::FeiDone != 0
+        })
+        .await?;
+        self.read_frequency_error(Register::FeiMsb).await
+    }
+
+    /// Reads a signed frequency error register pair and converts it to Hz
+    async fn read_frequency_error(&mut self, reg: Register) -> Result<i32, Error<E, RESET::Error, DIO0::Error>> {
+        let mut buffer = [0; 2];
+        self.read_registers(reg, &mut buffer).await?;
+        let steps = i16::from_be_bytes(buffer) as i64;
+        Ok((steps * self.fstep() as i64 / F_SCALE as i64) as i32)
+    }
+
     /// Sets preamble length in corresponding registers
     pub async fn preamble_length(&mut self, length: u16) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
         self.write_registers(Register::PreambleMsb, &length.to_be_bytes()).await
@@ -143,10 +396,19 @@ where
     }
 
     /// Sets packet settings in corresponding registers
+    ///
+    /// Packets larger than the fifo can only be received with dio1 or without any dio (see `recv`).
+    /// With dio0 only, the payload length of variable length packets is limited to the fifo size, so
+    /// the radio drops larger ones, and larger fixed length packets are rejected with
+    /// `Error::PacketSize`.
+    ///
+    /// `send` and `recv` use the packet format: variable length packets start with a length byte,
+    /// fixed length packets are padded to the fixed length.
     pub async fn packet(&mut self, packet_config: PacketConfig) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.check_packet_format(packet_config.format)?;
         let len: u8;
         let mut reg = 0x00;
-        match packet_config.format {
+        match self.rx_packet_format(packet_config.format) {
             PacketFormat::Fixed(size) => len = size,
             PacketFormat::Variable(size) => {
                 len = size;
@@ -155,10 +417,60 @@ where
         }
         reg |= packet_config.dc as u8 | packet_config.filtering as u8 | (packet_config.crc as u8) << 4;
         self.write_registers(Register::PacketConfig1, &[reg, len]).await?;
+        self.packet_format = packet_config.format;
         reg = packet_config.interpacket_rx_delay as u8 | (packet_config.auto_rx_restart as u8) << 1;
         self.update_register(Register::PacketConfig2, |r| r & 0x0d | reg).await
     }
 
+    /// Sets the node address, that `PacketFiltering::Address` and `PacketFiltering::Broadcast` accept
+    ///
+    /// The radio compares it with the first byte of the frame, after the length byte.
+    pub async fn node_address(&mut self, address: u8) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.write_register(Register::NodeAdrs, address).await
+    }
+
+    /// Sets the broadcast address, that `PacketFiltering::Broadcast` accepts in addition to the node
+    /// address
+    pub async fn broadcast_address(&mut self, address: u8) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.write_register(Register::BroadcastAdrs, address).await
+    }
+
+    /// Sets the aes key and enables encryption
+    ///
+    /// Pass `None` to disable encryption. While encryption is enabled, packets must fit into the fifo,
+    /// so the packet length (without the length byte) is limited to 64 bytes.
+    pub async fn aes(&mut self, key: Option<&[u8; 16]>) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        match key {
+            Some(key) => {
+                self.write_registers(Register::AesKey1, key).await?;
+                self.update_register(Register::PacketConfig2, |r| r | 0x01).await?;
+            }
+            None => self.update_register(Register::PacketConfig2, |r| r & 0xfe).await?,
+        }
+        self.aes = key.is_some();
+        Ok(())
+    }
+
+    /// Sets the output power and the power amplifiers in corresponding registers
+    ///
+    /// With `OutputPower::Pa1Pa2Boost` the overcurrent protection is disabled and the high power
+    /// settings are enabled during tx only, as required by the datasheet.
+    pub async fn output_power(&mut self, power: OutputPower) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        if !power.is_available(self.variant) {
+            return Err(Error::OutputPower);
+        }
+        let reg = power.value().ok_or(Error::OutputPower)?;
+        self.high_power = power.is_high_power();
+        self.write_register(Register::Ocp, if self.high_power { OCP_OFF } else { OCP_ON })
+            .await?;
+        self.write_register(Register::PaLevel, reg).await
+    }
+
+    /// Sets the output power in dBm, using the power amplifiers of the configured variant
+    pub async fn tx_power(&mut self, dbm: i8) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.output_power(OutputPower::from_dbm(self.variant, dbm)).await
+    }
+
     /// Sets fifo mode in corresponding register
     pub async fn fifo_mode(&mut self, mode: FifoMode) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
         match mode {
@@ -183,6 +495,316 @@ where
         self.write_register(Register::TestDagc, cdagc as u8).await
     }
 
+    /// Validates and applies a complete configuration
+    ///
+    /// Nothing is written if the configuration is invalid. The radio is put into standby mode while
+    /// the configuration is written and stays there afterwards. The configuration is kept to restore
+    /// it, if the radio needs to be recovered.
+    pub async fn apply<RxBwT>(&mut self, config: &Rfm69Config<RxBwT>) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq + Copy,
+    {
+        config.validate().map_err(Error::Config)?;
+        if OutputPower::from_dbm(self.variant, config.tx_power).value().is_none() {
+            return Err(Error::OutputPower);
+        }
+        self.check_packet_format(config.packet.format)?;
+        let rx_bw = RxBw {
+            dcc_cutoff: config.rx_bw.dcc_cutoff,
+            rx_bw: RxBwValue::new(config.rx_bw.rx_bw.value(), config.modulation.modulation_type),
+        };
+        let config = config.clone().rx_bw(rx_bw);
+        self.restore_interrupted().await?;
+        self.write_config(&config).await?;
+        self.config = Some(config);
+        Ok(())
+    }
+
+    /// Writes a validated configuration
+    async fn write_config(
+        &mut self,
+        config: &Rfm69Config<RxBwValue>,
+    ) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.set_mode(OpMode::Standby).await?;
+        self.modulation(config.modulation).await?;
+        self.bit_rate(config.bit_rate).await?;
+        self.fdev(config.fdev).await?;
+        self.rx_bw(config.rx_bw).await?;
+        self.preamble_length(config.preamble_length).await?;
+        self.sync(&config.sync).await?;
+        self.packet(config.packet).await?;
+        self.node_address(config.node_address).await?;
+        self.broadcast_address(config.broadcast_address).await?;
+        self.fifo_mode(config.fifo_mode).await?;
+        self.lna(config.lna).await?;
+        self.rssi_threshold(config.rssi_threshold).await?;
+        self.tx_power(config.tx_power).await?;
+        self.frequency(config.frequency).await?;
+        self.continuous_dagc(config.continuous_dagc).await?;
+        self.aes(config.aes_key.as_ref()).await
+    }
+
+    /// Reads the modulation from corresponding register
+    pub async fn read_modulation(&mut self) -> Result<Modulation, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.read_register(Register::DataModul).await?;
+        Modulation::from_value(reg).ok_or(Error::RegisterValue(Register::DataModul.addr()))
+    }
+
+    /// Reads the data bitrate from corresponding registers
+    pub async fn read_bit_rate(&mut self) -> Result<u32, Error<E, RESET::Error, DIO0::Error>> {
+        let mut buffer = [0; 2];
+        self.read_registers(Register::BitrateMsb, &mut buffer).await?;
+        Ok(self.bit_rate_from_reg(u16::from_be_bytes(buffer)))
+    }
+
+    /// Reads the radio frequency from corresponding registers
+    pub async fn read_frequency(&mut self) -> Result<u32, Error<E, RESET::Error, DIO0::Error>> {
+        let mut buffer = [0; 4];
+        self.read_registers(Register::FrfMsb, &mut buffer[1..]).await?;
+        Ok(self.fstep_from_reg(u32::from_be_bytes(buffer)))
+    }
+
+    /// Reads the frequency deviation from corresponding registers
+    pub async fn read_fdev(&mut self) -> Result<u32, Error<E, RESET::Error, DIO0::Error>> {
+        let mut buffer = [0; 2];
+        self.read_registers(Register::FdevMsb, &mut buffer).await?;
+        Ok(self.fstep_from_reg(u16::from_be_bytes(buffer) as u32 & 0x3fff))
+    }
+
+    /// Reads the rx bandwidth from corresponding register
+    ///
+    /// The type of the bandwidth must match the modulation type (`RxBwFsk` or `RxBwOok`), otherwise
+    /// `Error::RegisterValue` is returned.
+    pub async fn read_rx_bw<RxBwT>(&mut self) -> Result<RxBw<RxBwT>, Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq,
+    {
+        let modulation = self.read_modulation().await?;
+        let reg = self.read_register(Register::RxBw).await?;
+        RxBw::from_value(reg, modulation.modulation_type).ok_or(Error::RegisterValue(Register::RxBw.addr()))
+    }
+
+    /// Reads preamble length from corresponding registers
+    pub async fn read_preamble_length(&mut self) -> Result<u16, Error<E, RESET::Error, DIO0::Error>> {
+        let mut buffer = [0; 2];
+        self.read_registers(Register::PreambleMsb, &mut buffer).await?;
+        Ok(u16::from_be_bytes(buffer))
+    }
+
+    /// Reads sync words from corresponding registers
+    ///
+    /// Returns an empty buffer if the sync word detection is disabled.
+    pub async fn read_sync(&mut self) -> Result<Vec<u8, 8>, Error<E, RESET::Error, DIO0::Error>> {
+        let config = self.read_register(Register::SyncConfig).await?;
+        let mut sync = Vec::new();
+        if config & 0x80 != 0 {
+            let len = ((config >> 3) & 0x07) as usize + 1;
+            let mut buffer = [0; 8];
+            self.read_registers(Register::SyncValue1, &mut buffer[..len]).await?;
+            sync.extend_from_slice(&buffer[..len]).ok();
+        }
+        Ok(sync)
+    }
+
+    /// Reads packet settings from corresponding registers
+    pub async fn read_packet_config(&mut self) -> Result<PacketConfig, Error<E, RESET::Error, DIO0::Error>> {
+        let mut buffer = [0; 2];
+        self.read_registers(Register::PacketConfig1, &mut buffer).await?;
+        let config2 = self.read_register(Register::PacketConfig2).await?;
+        PacketConfig::from_values(buffer[0], buffer[1], config2)
+            .ok_or(Error::RegisterValue(Register::PacketConfig1.addr()))
+    }
+
+    /// Reads the node address
+    pub async fn read_node_address(&mut self) -> Result<u8, Error<E, RESET::Error, DIO0::Error>> {
+        self.read_register(Register::NodeAdrs).await
+    }
+
+    /// Reads the broadcast address
+    pub async fn read_broadcast_address(&mut self) -> Result<u8, Error<E, RESET::Error, DIO0::Error>> {
+        self.read_register(Register::BroadcastAdrs).await
+    }
+
+    /// Reads the aes key, `None` if encryption is disabled
+    pub async fn read_aes(&mut self) -> Result<Option<[u8; 16]>, Error<E, RESET::Error, DIO0::Error>> {
+        if self.read_register(Register::PacketConfig2).await? & 0x01 == 0 {
+            return Ok(None);
+        }
+        let mut key = [0; 16];
+        self.read_registers(Register::AesKey1, &mut key).await?;
+        Ok(Some(key))
+    }
+
+    /// Reads the output power and the power amplifiers from corresponding register
+    pub async fn read_output_power(&mut self) -> Result<OutputPower, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.read_register(Register::PaLevel).await?;
+        OutputPower::from_value(reg, self.high_power).ok_or(Error::RegisterValue(Register::PaLevel.addr()))
+    }
+
+    /// Reads fifo mode from corresponding register
+    pub async fn read_fifo_mode(&mut self) -> Result<FifoMode, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.read_register(Register::FifoThresh).await?;
+        Ok(FifoMode::from_value(reg))
+    }
+
+    /// Reads lna configuration from corresponding register
+    pub async fn read_lna(&mut self) -> Result<LnaConfig, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.read_register(Register::Lna).await?;
+        LnaConfig::from_value(reg).ok_or(Error::RegisterValue(Register::Lna.addr()))
+    }
+
+    /// Reads rssi threshold from corresponding register
+    pub async fn read_rssi_threshold(&mut self) -> Result<u8, Error<E, RESET::Error, DIO0::Error>> {
+        self.read_register(Register::RssiThresh).await
+    }
+
+    /// Reads continuous dagc from corresponding register
+    pub async fn read_continuous_dagc(&mut self) -> Result<ContinuousDagc, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.read_register(Register::TestDagc).await?;
+        ContinuousDagc::from_value(reg).ok_or(Error::RegisterValue(Register::TestDagc.addr()))
+    }
+
+    /// Reads the complete configuration back from the radio
+    ///
+    /// Frequency, frequency deviation and bitrate are the actual values, which may be slightly off
+    /// from the values that were set. The type of the rx bandwidth must match the modulation type.
+    pub async fn read_config<RxBwT>(&mut self) -> Result<Rfm69Config<RxBwT>, Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq,
+    {
+        Ok(Rfm69Config {
+            modulation: self.read_modulation().await?,
+            bit_rate: self.read_bit_rate().await?,
+            fdev: self.read_fdev().await?,
+            rx_bw: self.read_rx_bw().await?,
+            preamble_length: self.read_preamble_length().await?,
+            sync: self.read_sync().await?,
+            packet: self.read_packet_config().await?,
+            node_address: self.read_node_address().await?,
+            broadcast_address: self.read_broadcast_address().await?,
+            fifo_mode: self.read_fifo_mode().await?,
+            lna: self.read_lna().await?,
+            rssi_threshold: self.read_rssi_threshold().await?,
+            continuous_dagc: self.read_continuous_dagc().await?,
+            tx_power: self.read_output_power().await?.dbm(),
+            frequency: self.read_frequency().await?,
+            aes_key: self.read_aes().await?,
+        })
+    }
+
+    /// Returns true, if the radio still runs with the configuration
+    ///
+    /// The configuration is read back and compared with the given one, taking the precision of
+    /// frequency, frequency deviation and bitrate and the payload length limit of `packet` into
+    /// account. A radio that lost its settings, e.g. due to a brownout, has its reset defaults and
+    /// does not match.
+    pub async fn is_configured<RxBwT>(
+        &mut self,
+        config: &Rfm69Config<RxBwT>,
+    ) -> Result<bool, Error<E, RESET::Error, DIO0::Error>>
+    where
+        RxBwT: RxBwFreq + Clone + PartialEq,
+    {
+        let mut expected = config.clone();
+        expected.bit_rate = self.bit_rate_from_reg(self.bit_rate_reg(config.bit_rate));
+        expected.fdev = self.fstep_from_reg(self.fstep_reg(config.fdev));
+        expected.frequency = self.fstep_from_reg(self.fstep_reg(config.frequency));
+        expected.packet.format = self.rx_packet_format(config.packet.format);
+        let actual = match self.read_config().await {
+            Ok(actual) => actual,
+            Err(Error::RegisterValue(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        Ok(actual == expected)
+    }
+
+    /// Checks if the radio is still working as expected and recovers it otherwise
+    ///
+    /// The radio is recovered, if the version register is wrong, the radio is not in the mode it was
+    /// set to, or the configuration written with `apply` was lost. Call this periodically, e.g. while
+    /// the radio is idle. Returns the cause, if the radio was recovered.
+    pub async fn supervise(&mut self) -> Result<Option<RecoveryCause>, Error<E, RESET::Error, DIO0::Error>> {
+        self.restore_interrupted().await?;
+        let cause = self.check_health().await?;
+        if let Some(cause) = cause {
+            self.recover(cause).await?;
+        }
+        Ok(cause)
+    }
+
+    async fn check_health(&mut self) -> Result<Option<RecoveryCause>, Error<E, RESET::Error, DIO0::Error>> {
+        let version = self.read_register(Register::Version).await?;
+        if version != VERSION_CHECK {
+            return Ok(Some(RecoveryCause::Version(version)));
+        }
+
+        let mode = self.read_register(Register::OpMode).await?;
+        let stuck = if let OpMode::ListenOn = self.mode {
+            mode & OpMode::ListenOn.value() == 0
+        } else {
+            mode & 0x1c != self.mode.value() || !self.is_mode_ready().await?
+        };
+        if stuck {
+            return Ok(Some(RecoveryCause::StuckMode));
+        }
+
+        if let Some(config) = self.config.clone() {
+            if !self.is_configured(&config).await? {
+                return Ok(Some(RecoveryCause::ConfigLost));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Resets the radio with the reset pin and restores the last configuration
+    ///
+    /// Afterwards the radio is in standby mode.
+    pub async fn recover(&mut self, cause: RecoveryCause) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        log::warn!("Recovering radio: {:?}", cause);
+        self.timeouts_in_row = 0;
+        self.recoveries = self.recoveries.wrapping_add(1);
+        self.reset().await?;
+        if let Some(config) = self.config.take() {
+            let result = self.write_config(&config).await;
+            self.config = Some(config);
+            result?;
+        }
+        self.set_mode(OpMode::Standby).await
+    }
+
+    /// Returns the amount of recoveries since the instance was created
+    pub fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+
+    /// Returns the configuration last written with `apply`
+    pub(crate) fn config(&self) -> Option<&Rfm69Config<RxBwValue>> {
+        self.config.as_ref()
+    }
+
+    /// Counts timeouts in a row and recovers the radio, if there are too many
+    ///
+    /// Returns `Error::Recovered` instead of the last timeout in that case.
+    async fn supervise_result<T>(
+        &mut self,
+        result: Result<T, Error<E, RESET::Error, DIO0::Error>>,
+    ) -> Result<T, Error<E, RESET::Error, DIO0::Error>> {
+        match result {
+            // No packet arrived in time, the radio is fine
+            Err(Error::Timeout(WaitState::PayloadReady)) => {}
+            Err(Error::Timeout(_)) => {
+                self.timeouts_in_row += 1;
+                if self.timeouts_in_row >= self.max_timeouts_in_row {
+                    self.recover(RecoveryCause::Timeouts).await?;
+                    return Err(Error::Recovered(RecoveryCause::Timeouts));
+                }
+            }
+            Ok(_) => self.timeouts_in_row = 0,
+            Err(_) => {}
+        }
+        result
+    }
+
     /// Return if irq flag ModeReady is set
     pub async fn is_mode_ready(&mut self) -> Result<bool, Error<E, RESET::Error, DIO0::Error>> {
         let reg = self.read_register(Register::IrqFlags1).await?;
@@ -263,9 +885,53 @@ where
     /// Send data over the radio
     ///
     /// This async function returns when all data is sent.
-    pub async fn send(&mut self, packet: &Packet) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
-        if self.dio0.is_some() {
-            // configure dio mapping 00, so PacketSent is on it
+    /// Packets larger than the fifo are streamed into the fifo, refilled every time the fifo level
+    /// drops below the fifo threshold. The receiver must be configured with a large enough payload
+    /// length (see `PacketFormat::Variable`). If aes is enabled, packets are not streamed and larger
+    /// packets are rejected with `Error::PacketSize`.
+    ///
+    /// In variable length mode the length byte is sent before the frame. In fixed length mode, frames
+    /// shorter than the fixed length are padded with zeros and longer ones are rejected with
+    /// `Error::PacketSize`.
+    ///
+    /// This function is cancel safe. If the future is dropped before it completes, the radio is put
+    /// back into standby by the next call to `send`, `recv`, `apply` or `supervise`, or by `abort`.
+    pub async fn send(&mut self, frame: &CODEC::Frame) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        // A frame that cannot be sent is rejected before the radio is touched. The frame is encoded
+        // behind the length byte.
+        let mut raw = [0_u8; MAX_FIFO_DATA];
+        let frame_len = self
+            .codec
+            .encode(frame, (&mut raw[1..]).try_into().unwrap())
+            .map_err(|_| Error::WrongPacketFormat)?;
+        let (raw, packet_len) = match self.packet_format {
+            PacketFormat::Variable(_) => {
+                raw[0] = frame_len as u8;
+                (&raw[..frame_len + 1], frame_len)
+            }
+            PacketFormat::Fixed(length) => {
+                let length = length as usize;
+                if frame_len > length {
+                    return Err(Error::PacketSize);
+                }
+                // The rest of the buffer is zero
+                (&raw[1..length + 1], length)
+            }
+        };
+        if self.aes && packet_len > MAX_AES_PACKET_LENGTH {
+            return Err(Error::PacketSize);
+        }
+
+        self.restore_interrupted().await?;
+        self.interrupted = true;
+        let result = self.send_packet(raw).await;
+        self.finish(result).await
+    }
+
+    async fn send_packet(&mut self, raw: &[u8]) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        let len = raw.len();
+        if self.dio0.is_some() || self.dio1.is_some() {
+            // configure dio mapping 00, so PacketSent is on dio0 and FifoLevel on dio1
             self.write_register(Register::DioMapping1, 0).await?;
         }
 
@@ -276,66 +942,451 @@ where
         self.delay.delay_ms(1).await;
 
         // ModeReady does not seem to work, if already in that mode
-        while !self.is_mode_ready().await? {
-            self.delay.delay_ms(500).await;
-            let mode = self.read_register(Register::OpMode).await?;
-            let irq1 = self.read_register(Register::IrqFlags1).await?;
-            let irq2 = self.read_register(Register::IrqFlags2).await?;
-            log::info!("OM 0x{:02x} - Irq1 0x{:02x} - Irq2 0x{:02x}", mode, irq1, irq2);
-        }
+        self.poll_register(
+            Register::IrqFlags1,
+            self.timeouts.mode_ready_us,
+            WaitState::ModeReady,
+            |r| r & IrqFlags1::ModeReady != 0,
+        )
+        .await?;
 
         self.reset_fifo().await?;
         self.delay.delay_ms(1).await;
 
-        let mut raw = [0_u8; 65];
-        let len = packet.to_slice(&mut raw).map_err(|_| Error::WrongPacketFormat)?;
-        self.write_registers(Register::Fifo, &raw[..len as usize]).await?;
+        if self.high_power {
+            self.test_pa(TEST_PA_HIGH_POWER).await?;
+        }
+
+        let mut written = len.min(FIFO_SIZE);
+        self.write_registers(Register::Fifo, &raw[..written]).await?;
 
         self.set_mode(OpMode::Tx).await?;
 
+        if written < len {
+            let threshold = self.fifo_threshold().await?;
+            while written < len {
+                self.wait_for_fifo_level_low().await?;
+                // The fifo contains at most threshold bytes now
+                let chunk = (len - written).min(FIFO_SIZE.saturating_sub(threshold).max(1));
+                self.write_registers(Register::Fifo, &raw[written..written + chunk])
+                    .await?;
+                written += chunk;
+                self.check_fifo_overrun().await?;
+            }
+        }
+
+        let timeout = self.timeouts.packet_sent_us;
         if let Some(dio0) = &mut self.dio0 {
-            dio0.wait_for_high().await.map_err(Error::DIO0)?;
+            with_timeout(&mut self.delay, Some(timeout), dio0.wait_for_high())
+                .await
+                .ok_or(Error::Timeout(WaitState::PacketSent))?
+                .map_err(Error::DIO0)?;
         } else {
-            while !self.is_packet_sent().await? {
-                //Timer::after(Duration::from_micros(500_u64)).await;
-            }
+            self.poll_register(Register::IrqFlags2, timeout, WaitState::PacketSent, |r| {
+                r & IrqFlags2::PacketSent != 0
+            })
+            .await?;
         }
         log::debug!("Packet Sent");
 
-        self.set_mode(OpMode::Standby).await
+        self.set_mode(OpMode::Standby).await?;
+
+        if self.high_power {
+            // The high power settings must not be used in rx mode
+            self.test_pa(TEST_PA_NORMAL).await?;
+        }
+        Ok(())
     }
 
     /// Receive data over the radio
     ///
     /// This async function returns once a complete packet is received.
-    pub async fn recv(&mut self) -> Result<Packet, Error<E, RESET::Error, DIO0::Error>> {
-        if self.dio0.is_some() {
-            // configure dio0 mapping 01, so PayloadReady is on it
+    /// Packets larger than the fifo are read while they are received, every time the fifo level
+    /// exceeds the fifo threshold. This requires dio1, or no dio0 at all so the interrupt register is
+    /// polled.
+    ///
+    /// This function is cancel safe, e.g. it can be wrapped in a timeout. If the future is dropped
+    /// before it completes, the radio is put back into standby by the next call to `send`, `recv`,
+    /// `apply` or `supervise`, or by `abort`.
+    pub async fn recv(&mut self) -> Result<CODEC::Frame, Error<E, RESET::Error, DIO0::Error>> {
+        self.restore_interrupted().await?;
+        self.interrupted = true;
+        let result = self.recv_packet().await;
+        self.finish(result).await
+    }
+
+    async fn recv_packet(&mut self) -> Result<CODEC::Frame, Error<E, RESET::Error, DIO0::Error>> {
+        self.map_dio_rx().await?;
+
+        self.set_mode(OpMode::Rx).await?;
+
+        let mut buffer = [0; MAX_FIFO_DATA];
+        let received = self.stream_rx(&mut buffer).await?;
+
+        self.set_mode(OpMode::Standby).await?;
+
+        self.read_packet(&mut buffer, received).await
+    }
+
+    /// Configure listen mode in corresponding registers
+    pub async fn listen(&mut self, config: ListenConfig) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.write_registers(
+            Register::Listen1,
+            &[config.value(), config.idle_coefficient, config.rx_coefficient],
+        )
+        .await
+    }
+
+    /// Enters listen mode
+    ///
+    /// The radio periodically wakes up to receive, as configured with `listen`, while the mcu can
+    /// sleep. Use `listen_recv` to wait for a packet.
+    pub async fn start_listen(&mut self) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.restore_interrupted().await?;
+        self.map_dio_rx().await?;
+        self.set_mode(OpMode::Standby).await?;
+        self.write_register(Register::OpMode, OpMode::ListenOn.value() | OpMode::Standby.value())
+            .await?;
+        self.mode = OpMode::ListenOn;
+        Ok(())
+    }
+
+    /// Leaves listen mode into standby
+    pub async fn stop_listen(&mut self) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        // Listen mode must be aborted and cleared in two separate accesses
+        self.write_register(Register::OpMode, OpMode::ListenAbort.value() | OpMode::Standby.value())
+            .await?;
+        self.set_mode(OpMode::Standby).await
+    }
+
+    /// Receive data in listen mode
+    ///
+    /// Listen mode is configured with `listen` and entered with `start_listen`. If the radio is not
+    /// in listen mode, e.g. because the previous call received a packet or failed, listen mode is
+    /// entered again. This async function returns once a complete packet is received. Afterwards the
+    /// radio left listen mode and is in standby.
+    ///
+    /// This function is cancel safe. If a previous call was cancelled while a packet was read,
+    /// listen mode is restarted with an empty fifo.
+    pub async fn listen_recv(&mut self) -> Result<CODEC::Frame, Error<E, RESET::Error, DIO0::Error>> {
+        self.restore_interrupted().await?;
+        if !matches!(self.mode, OpMode::ListenOn) {
+            self.start_listen().await?;
+        }
+        self.interrupted = true;
+        let result = self.listen_recv_packet().await;
+        self.finish(result).await
+    }
+
+    async fn listen_recv_packet(&mut self) -> Result<CODEC::Frame, Error<E, RESET::Error, DIO0::Error>> {
+        let mut buffer = [0; MAX_FIFO_DATA];
+        let received = self.stream_rx(&mut buffer).await?;
+
+        self.stop_listen().await?;
+
+        self.read_packet(&mut buffer, received).await
+    }
+
+    async fn map_dio_rx(&mut self) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        if self.dio0.is_some() || self.dio1.is_some() {
+            // configure dio0 mapping 01, so PayloadReady is on it and FifoLevel on dio1
             self.write_register(Register::DioMapping1, 0x40).await?;
         }
+        Ok(())
+    }
 
-        self.set_mode(OpMode::Rx).await?;
+    /// Reads the fifo while a packet is received, until it is completely received
+    ///
+    /// Waiting for the start of the packet is bounded by the rx timeout, the following fifo
+    /// levels by the fifo timeout. Returns the amount of bytes read into the buffer.
+    async fn stream_rx(
+        &mut self,
+        buffer: &mut [u8; MAX_FIFO_DATA],
+    ) -> Result<usize, Error<E, RESET::Error, DIO0::Error>> {
+        let threshold = self.fifo_threshold().await?;
+        let mut received = 0;
+        let mut timeout = self.timeouts.rx_us;
+        while let RxEvent::FifoLevel = self.wait_for_rx_event(timeout, received > 0).await? {
+            // The fifo contains more than threshold bytes
+            let chunk = threshold.max(1).min(MAX_FIFO_DATA - received);
+            self.read_registers(Register::Fifo, &mut buffer[received..received + chunk])
+                .await?;
+            received += chunk;
+            self.check_fifo_overrun().await?;
+            timeout = Some(self.timeouts.fifo_level_us);
+        }
+        Ok(received)
+    }
 
-        if let Some(dio0) = &mut self.dio0 {
-            dio0.wait_for_high().await.map_err(Error::DIO0)?;
+    /// Reads the rest of a completely received packet from the fifo
+    async fn read_packet(
+        &mut self,
+        buffer: &mut [u8; MAX_FIFO_DATA],
+        mut received: usize,
+    ) -> Result<CODEC::Frame, Error<E, RESET::Error, DIO0::Error>> {
+        let (start, total) = match self.packet_format {
+            PacketFormat::Variable(_) => {
+                // First byte in fifo is length, because af variable packet length.
+                if received == 0 {
+                    buffer[0] = self.read_register(Register::Fifo).await?;
+                    received = 1;
+                }
+                (1, buffer[0] as usize + 1)
+            }
+            PacketFormat::Fixed(length) => (0, length as usize),
+        };
+        if received < total {
+            self.read_registers(Register::Fifo, &mut buffer[received..total])
+                .await?;
+        }
+        // Bytes were lost, if the fifo was not read in time
+        self.check_fifo_overrun().await?;
+        let rx = RxInfo {
+            rssi: self.read_rssi().await?,
+            afc: self.read_frequency_error(Register::AfcMsb).await?,
+            fei: self.read_frequency_error(Register::FeiMsb).await?,
+        };
+
+        log::debug!("Rx: Rssi {}; Len {}", rx.rssi, total - start);
+
+        self.codec
+            .decode(&buffer[start..total], rx)
+            .map_err(|_| Error::WrongPacketFormat)
+    }
+
+    /// Returns true, if only packets that fit into the fifo can be received
+    ///
+    /// With dio0 but without dio1, the driver waits for PayloadReady on dio0 and does not see the
+    /// fifo level while a packet is received.
+    fn is_rx_fifo_limited(&self) -> bool {
+        self.dio0.is_some() && self.dio1.is_none()
+    }
+
+    /// Returns the packet format as it is written to the registers, see `packet`
+    fn rx_packet_format(&self, format: PacketFormat) -> PacketFormat {
+        match format {
+            // The length byte is part of the fifo content
+            PacketFormat::Variable(size) if self.is_rx_fifo_limited() => {
+                PacketFormat::Variable(size.min((FIFO_SIZE - 1) as u8))
+            }
+            format => format,
+        }
+    }
+
+    /// Returns `Error::PacketSize`, if fixed length packets of the format cannot be received
+    fn check_packet_format(&self, format: PacketFormat) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        match format {
+            PacketFormat::Fixed(length) if self.is_rx_fifo_limited() && length as usize > FIFO_SIZE => {
+                Err(Error::PacketSize)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `Error::FifoOverrun`, if data in the fifo was lost
+    async fn check_fifo_overrun(&mut self) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        if self.read_register(Register::IrqFlags2).await? & IrqFlags2::FifoOverrun != 0 {
+            // Clearing the flag also clears the fifo
+            self.reset_fifo().await?;
+            return Err(Error::FifoOverrun);
+        }
+        Ok(())
+    }
+
+    async fn test_pa(&mut self, values: [u8; 2]) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        self.write_register(Register::TestPa1, values[0]).await?;
+        self.write_register(Register::TestPa2, values[1]).await
+    }
+
+    /// Returns the configured fifo threshold
+    async fn fifo_threshold(&mut self) -> Result<usize, Error<E, RESET::Error, DIO0::Error>> {
+        let reg = self.read_register(Register::FifoThresh).await?;
+        Ok((reg & 0x7f) as usize)
+    }
+
+    /// Waits until the fifo level drops to the fifo threshold or below
+    async fn wait_for_fifo_level_low(&mut self) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        let timeout = self.timeouts.fifo_level_us;
+        if let Some(dio1) = &mut self.dio1 {
+            with_timeout(&mut self.delay, Some(timeout), dio1.wait_for_low())
+                .await
+                .ok_or(Error::Timeout(WaitState::FifoLevel))?
+                .map_err(Error::DIO1)
         } else {
-            while !self.is_packet_ready().await? {
-                self.delay.delay_us(500).await;
+            self.poll_register(Register::IrqFlags2, timeout, WaitState::FifoLevel, |r| {
+                r & IrqFlags2::FifoLevel == 0
+            })
+            .await
+        }
+    }
+
+    /// Waits until the packet is completely received or the fifo level exceeds the fifo threshold
+    ///
+    /// Waits at most `timeout_us`, if given. `started` tells if parts of the packet were read already.
+    async fn wait_for_rx_event(
+        &mut self,
+        timeout_us: Option<u32>,
+        started: bool,
+    ) -> Result<RxEvent, Error<E, RESET::Error, DIO0::Error>> {
+        let event = match (&mut self.dio0, &mut self.dio1) {
+            (Some(dio0), Some(dio1)) => with_timeout(
+                &mut self.delay,
+                timeout_us,
+                select(dio0.wait_for_high(), dio1.wait_for_high()),
+            )
+            .await
+            .map(|either| match either {
+                Either::First(result) => result.map(|_| RxEvent::PayloadReady).map_err(Error::DIO0),
+                Either::Second(result) => result.map(|_| RxEvent::FifoLevel).map_err(Error::DIO1),
+            }),
+            (Some(dio0), None) => with_timeout(&mut self.delay, timeout_us, dio0.wait_for_high())
+                .await
+                .map(|result| result.map(|_| RxEvent::PayloadReady).map_err(Error::DIO0)),
+            (None, _) => {
+                let mut waited = 0_u32;
+                loop {
+                    let reg = self.read_register(Register::IrqFlags2).await?;
+                    if reg & IrqFlags2::PayloadReady != 0 {
+                        break Some(Ok(RxEvent::PayloadReady));
+                    } else if reg & IrqFlags2::FifoLevel != 0 {
+                        break Some(Ok(RxEvent::FifoLevel));
+                    } else if timeout_us.is_some_and(|timeout| waited >= timeout) {
+                        break None;
+                    }
+                    self.delay.delay_us(POLL_INTERVAL_US).await;
+                    waited = waited.saturating_add(POLL_INTERVAL_US);
+                }
+            }
+        };
+        let state = if started {
+            WaitState::FifoLevel
+        } else {
+            WaitState::PayloadReady
+        };
+        event.unwrap_or(Err(Error::Timeout(state)))
+    }
+
+    /// Polls the register until `done` returns true, at most for `timeout_us`
+    async fn poll_register<F>(
+        &mut self,
+        reg: Register,
+        timeout_us: u32,
+        state: WaitState,
+        done: F,
+    ) -> Result<(), Error<E, RESET::Error, DIO0::Error>>
+    where
+        F: Fn(u8) -> bool,
+    {
+        let mut waited = 0_u32;
+        while !done(self.read_register(reg).await?) {
+            if waited >= timeout_us {
+                log::warn!("Timeout waiting for {:?}", state);
+                return Err(Error::Timeout(state));
             }
+            self.delay.delay_us(POLL_INTERVAL_US).await;
+            waited = waited.saturating_add(POLL_INTERVAL_US);
         }
+        Ok(())
+    }
 
+    /// Brings the radio back into a defined state after a cancelled or failed transmission or reception
+    ///
+    /// The radio leaves listen mode and is put into standby, the fifo is cleared, the high power
+    /// settings are disabled and the dio mapping is reset.
+    pub async fn abort(&mut self) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        if let OpMode::ListenOn = self.mode {
+            self.write_register(Register::OpMode, OpMode::ListenAbort.value() | OpMode::Standby.value())
+                .await?;
+        }
         self.set_mode(OpMode::Standby).await?;
+        self.reset_fifo().await?;
+        if self.high_power {
+            self.test_pa(TEST_PA_NORMAL).await?;
+        }
+        if self.dio0.is_some() || self.dio1.is_some() {
+            self.write_register(Register::DioMapping1, 0).await?;
+        }
+        self.interrupted = false;
+        Ok(())
+    }
 
-        // First byte in fifo is length, because af variable packet length.
-        let len = self.read_register(Register::Fifo).await?;
-        let mut buffer = [0; 64];
-        self.read_registers(Register::Fifo, &mut buffer[..len as usize]).await?;
-        let rssi = self.read_rssi().await?;
+    /// Aborts a send or receive that was cancelled before it completed
+    async fn restore_interrupted(&mut self) -> Result<(), Error<E, RESET::Error, DIO0::Error>> {
+        if self.interrupted {
+            log::warn!("Restoring radio state after cancelled operation");
+            self.abort().await?;
+        }
+        Ok(())
+    }
 
-        let packet = Packet::from_rx_data(len, &buffer, rssi).map_err(|_| Error::WrongPacketFormat)?;
+    /// Completes a send or receive
+    ///
+    /// On timeouts and fifo overruns the radio is aborted into standby. After other errors the radio
+    /// state is unknown, so it is aborted with the next operation.
+    async fn finish<T>(
+        &mut self,
+        result: Result<T, Error<E, RESET::Error, DIO0::Error>>,
+    ) -> Result<T, Error<E, RESET::Error, DIO0::Error>> {
+        match result {
+            Ok(_) => self.interrupted = false,
+            Err(Error::Timeout(_) | Error::FifoOverrun) => self.abort().await?,
+            Err(_) => {}
+        }
+        self.supervise_result(result).await
+    }
+}
 
-        log::debug!("Rx: Rssi {}; Len {}", rssi, len);
+/// Events while receiving a packet
+enum RxEvent {
+    PayloadReady,
+    FifoLevel,
+}
 
-        Ok(packet)
+pub(crate) enum Either<A, B> {
+    First(A),
+    Second(B),
+}
+
+/// Waits for the future, at most `timeout_us` if given
+///
+/// Returns `None` if the timeout elapsed first.
+async fn with_timeout<D: DelayUs, F: Future>(delay: &mut D, timeout_us: Option<u32>, future: F) -> Option<F::Output> {
+    match timeout_us {
+        Some(timeout) => match select(future, delay.delay_us(timeout)).await {
+            Either::First(result) => Some(result),
+            Either::Second(()) => None,
+        },
+        None => Some(future.await),
     }
 }
+
+/// Returns pending once, to let other futures run
+pub(crate) async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Waits for the first of both futures to complete
+pub(crate) async fn select<A: Future, B: Future>(a: A, b: B) -> Either<A::Output, B::Output> {
+    let mut a = pin!(a);
+    let mut b = pin!(b);
+    poll_fn(|cx| {
+        if let Poll::Ready(result) = a.as_mut().poll(cx) {
+            Poll::Ready(Either::First(result))
+        } else if let Poll::Ready(result) = b.as_mut().poll(cx) {
+            Poll::Ready(Either::Second(result))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
@@ -0,0 +1,423 @@
+//! Simulated radio medium shared by several simulated transceivers
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::{Poll, Waker};
+
+use heapless::Vec;
+
+use super::radio::{Frame, SimRadio};
+use super::SimBus;
+use crate::mac::Clock;
+use crate::registers::{AfcFei, Register};
+use crate::rfm::yield_now;
+
+/// Amount of frames that can be on air at the same time
+const MAX_FRAMES_IN_FLIGHT: usize = 16;
+
+/// Amount of delays a single node can wait for at the same time, e.g. a timeout and a poll interval
+const MAX_TIMERS_PER_NODE: usize = 4;
+
+/// Properties of the transmission from one node to another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    /// Probability in percent, that a frame is lost
+    pub loss: u8,
+    /// Probability in percent, that a single bit of a frame is flipped
+    pub corruption: u8,
+    /// Propagation delay in microseconds, added to the airtime of the frame
+    pub delay_us: u32,
+    /// Rssi the receiver reports for frames over this link
+    pub rssi: i16,
+    /// Frequency offset of the transmitter in Hz, as measured by fei and afc of the receiver
+    pub frequency_offset: i32,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            loss: 0,
+            corruption: 0,
+            delay_us: 0,
+            rssi: -60,
+            frequency_offset: 0,
+        }
+    }
+}
+
+impl Link {
+    /// Link over which no frame is received
+    pub fn disconnected() -> Self {
+        Self {
+            loss: 100,
+            ..Default::default()
+        }
+    }
+}
+
+/// Frame on its way from one node to another
+struct InFlight {
+    to: usize,
+    start_us: u64,
+    end_us: u64,
+    // Time the receiver starts to put the bytes after the sync words into its fifo
+    data_us: u64,
+    delivered: bool,
+    data: Frame,
+    rssi: i16,
+    frequency_offset: i32,
+    corrupted: bool,
+    collided: bool,
+}
+
+/// Deterministic pseudo random generator (xorshift64*)
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift must not be seeded with 0
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns true with the given probability in percent
+    fn chance(&mut self, percent: u8) -> bool {
+        (self.next() % 100) < u64::from(percent)
+    }
+}
+
+struct AirState<const N: usize> {
+    radios: [SimRadio; N],
+    links: [[Link; N]; N],
+    rng: Rng,
+    now_us: u64,
+    activity: u64,
+    timers: [Vec<(u64, Waker), MAX_TIMERS_PER_NODE>; N],
+    in_flight: Vec<InFlight, MAX_FRAMES_IN_FLIGHT>,
+}
+
+/// Simulated medium connecting `N` simulated radios
+///
+/// Frames sent by one radio are received by all other radios that are in rx mode and configured
+/// with the same frequency and sync words. Every link between two nodes can be configured with
+/// loss, corruption, delay and rssi. Frames that overlap in time at a receiver collide and are lost.
+///
+/// Time is simulated. It only advances, while all nodes are waiting for a delay or an interrupt.
+/// To drive it, `run` must be polled concurrently to the nodes. With the same seed and the same
+/// node behaviour a simulation is deterministic.
+pub struct Air<const N: usize> {
+    state: RefCell<AirState<N>>,
+}
+
+impl<const N: usize> Air<N> {
+    /// Returns a medium with `N` radios after power on reset and default links between them
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: RefCell::new(AirState {
+                radios: core::array::from_fn(|_| SimRadio::new()),
+                links: [[Link::default(); N]; N],
+                rng: Rng::new(seed),
+                now_us: 0,
+                activity: 0,
+                timers: core::array::from_fn(|_| Vec::new()),
+                in_flight: Vec::new(),
+            }),
+        }
+    }
+
+    /// Returns the node with the given index, to connect the simulated hal implementations to
+    pub fn node(&self, index: usize) -> AirNode<'_, N> {
+        assert!(index < N);
+        AirNode { air: self, index }
+    }
+
+    /// Configures the link from node `from` to node `to`
+    pub fn set_link(&self, from: usize, to: usize, link: Link) {
+        self.state.borrow_mut().links[from][to] = link;
+    }
+
+    /// Configures the links in both directions between two nodes
+    pub fn set_links(&self, a: usize, b: usize, link: Link) {
+        self.set_link(a, b, link);
+        self.set_link(b, a, link);
+    }
+
+    /// Returns the current simulated time
+    pub fn now_us(&self) -> u64 {
+        self.state.borrow().now_us
+    }
+
+    /// Drives the simulated time
+    ///
+    /// This never returns, it must be run concurrently to the nodes, e.g. with `select`.
+    pub async fn run(&self) {
+        let mut last_activity = None;
+        loop {
+            {
+                let mut state = self.state.borrow_mut();
+                if last_activity == Some(state.activity) {
+                    // Nobody accessed a radio since the last poll, so everybody waits
+                    state.advance();
+                }
+                last_activity = Some(state.activity);
+            }
+            yield_now().await;
+        }
+    }
+}
+
+/// The simulated time, e.g. for `mac::DelayTimer`
+impl<const N: usize> Clock for Air<N> {
+    fn now_us(&self) -> u64 {
+        Air::now_us(self)
+    }
+}
+
+impl<const N: usize> AirState<N> {
+    /// Puts all frames that the radios completed sending on air
+    fn collect_tx(&mut self, from: usize) {
+        while let Some(frame) = self.radios[from].pop_tx() {
+            let airtime = airtime_us(&self.radios[from], frame.len());
+            let data_time = frame.len() as u64 * self.radios[from].byte_us();
+            for to in 0..N {
+                if to == from || !same_channel(&self.radios[from], &self.radios[to]) {
+                    continue;
+                }
+                let link = self.links[from][to];
+                if self.rng.chance(link.loss) {
+                    continue;
+                }
+                let corrupted = self.rng.chance(link.corruption);
+                let mut data = frame.clone();
+                if !same_aes_key(&self.radios[from], &self.radios[to]) {
+                    // Decrypting with a different key results in garbage, the length byte is not encrypted
+                    for byte in data.iter_mut().skip(1) {
+                        *byte ^= self.rng.next() as u8;
+                    }
+                }
+                if corrupted && !data.is_empty() {
+                    let bit = self.rng.next() as usize % (data.len() * 8);
+                    data[bit / 8] ^= 1 << (bit % 8);
+                }
+                let start_us = self.now_us + u64::from(link.delay_us);
+                let end_us = start_us + airtime;
+                let mut frame = InFlight {
+                    to,
+                    start_us,
+                    end_us,
+                    data_us: end_us.saturating_sub(data_time).max(start_us),
+                    delivered: false,
+                    data,
+                    rssi: link.rssi,
+                    frequency_offset: link.frequency_offset,
+                    corrupted,
+                    collided: false,
+                };
+                for other in self.in_flight.iter_mut() {
+                    if other.to == to && other.start_us < frame.end_us && frame.start_us < other.end_us {
+                        if other.delivered && !other.collided {
+                            self.radios[to].abort_rx();
+                        }
+                        other.collided = true;
+                        frame.collided = true;
+                    }
+                }
+                if self.in_flight.push(frame).is_err() {
+                    log::warn!("Too many frames on air, dropping frame");
+                }
+            }
+        }
+    }
+
+    /// Advances the time to the next event and handles all events that are due
+    fn advance(&mut self) {
+        let now = self.now_us;
+        let next_timer = self.timers.iter().flatten().map(|(deadline, _)| *deadline);
+        let next_frame = self
+            .in_flight
+            .iter()
+            .map(|frame| if frame.delivered { frame.end_us } else { frame.data_us });
+        let next_byte = self
+            .radios
+            .iter()
+            .filter_map(|radio| radio.next_event_us().map(|us| now + us));
+        let Some(next) = next_timer.chain(next_frame).chain(next_byte).min() else {
+            return;
+        };
+        let next = next.max(now);
+        for radio in self.radios.iter_mut() {
+            radio.advance(next - now);
+        }
+        self.now_us = next;
+
+        let now = self.now_us;
+        for timers in self.timers.iter_mut() {
+            let mut i = 0;
+            while i < timers.len() {
+                if timers[i].0 <= now {
+                    let (_, waker) = timers.swap_remove(i);
+                    waker.wake();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        for i in 0..self.in_flight.len() {
+            if !self.in_flight[i].delivered && self.in_flight[i].data_us <= now {
+                self.in_flight[i].delivered = true;
+                let frame = &self.in_flight[i];
+                Self::deliver(&mut self.radios[frame.to], frame);
+            }
+        }
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            if self.in_flight[i].end_us <= now {
+                self.in_flight.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Starts the reception of the frame, if the receiving radio is able to receive it
+    fn deliver(radio: &mut SimRadio, frame: &InFlight) {
+        if frame.collided || !radio.is_receiving() {
+            return;
+        }
+        // The radio drops frames with wrong crc
+        if frame.corrupted && radio.is_crc_on() {
+            return;
+        }
+        let threshold = -i16::from(radio.reg(Register::RssiThresh.addr())) / 2;
+        if frame.rssi < threshold {
+            return;
+        }
+        // Frequency errors are given in steps of FOSC / 2^19
+        let steps = (i64::from(frame.frequency_offset) * 524_288 / 32_000_000) as i16;
+        let [msb, lsb] = steps.to_be_bytes();
+        radio.set_reg(Register::FeiMsb.addr(), msb);
+        radio.set_reg(Register::FeiLsb.addr(), lsb);
+        if radio.reg(Register::AfcFei.addr()) & AfcFei::AfcAutoOn != 0 {
+            radio.set_reg(Register::AfcMsb.addr(), msb);
+            radio.set_reg(Register::AfcLsb.addr(), lsb);
+        }
+        radio.push_rx(&frame.data, frame.rssi);
+    }
+}
+
+/// One radio of an `Air`
+#[derive(Clone, Copy)]
+pub struct AirNode<'a, const N: usize> {
+    air: &'a Air<N>,
+    index: usize,
+}
+
+impl<'a, const N: usize> SimBus for AirNode<'a, N> {
+    fn with_radio<R>(&self, f: impl FnOnce(&mut SimRadio) -> R) -> R {
+        f(&mut self.air.state.borrow_mut().radios[self.index])
+    }
+
+    fn accessed(&self) {
+        let mut state = self.air.state.borrow_mut();
+        state.activity += 1;
+        state.collect_tx(self.index);
+    }
+
+    fn idle(&self) -> bool {
+        // The time only advances in `Air::run`
+        false
+    }
+
+    async fn delay_us(&self, us: u32) {
+        let deadline = {
+            let mut state = self.air.state.borrow_mut();
+            state.radios[self.index].elapse(us);
+            state.now_us + u64::from(us)
+        };
+        poll_fn(|cx| {
+            let mut state = self.air.state.borrow_mut();
+            let now = state.now_us;
+            let timers = &mut state.timers[self.index];
+            // Each delay owns the entry with its deadline and waker, concurrent delays get their own
+            let own = timers
+                .iter()
+                .position(|(d, waker)| *d == deadline && waker.will_wake(cx.waker()));
+            if now >= deadline {
+                if let Some(i) = own {
+                    timers.swap_remove(i);
+                }
+                return Poll::Ready(());
+            }
+            match own {
+                Some(i) => timers[i].1 = cx.waker().clone(),
+                None => {
+                    if timers.push((deadline, cx.waker().clone())).is_err() {
+                        // Poll again, the time still advances while the node does not access its radio
+                        log::warn!("Too many concurrent delays on node {}", self.index);
+                        cx.waker().wake_by_ref();
+                    }
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Returns true, if both radios use the same frequency and sync words
+fn same_channel(a: &SimRadio, b: &SimRadio) -> bool {
+    let frf = Register::FrfMsb.addr();
+    if (frf..frf + 3).any(|reg| a.reg(reg) != b.reg(reg)) {
+        return false;
+    }
+    let config = Register::SyncConfig.addr();
+    if a.reg(config) & 0xb8 != b.reg(config) & 0xb8 {
+        return false;
+    }
+    if a.reg(config) & 0x80 == 0 {
+        return true;
+    }
+    let sync = Register::SyncValue1.addr();
+    let len = ((a.reg(config) >> 3) & 0x07) + 1;
+    (sync..sync + len).all(|reg| a.reg(reg) == b.reg(reg))
+}
+
+/// Returns true, if both radios use the same aes settings
+fn same_aes_key(a: &SimRadio, b: &SimRadio) -> bool {
+    let config = Register::PacketConfig2.addr();
+    let aes_on = a.reg(config) & 0x01;
+    if aes_on != b.reg(config) & 0x01 {
+        return false;
+    }
+    let key = Register::AesKey1.addr();
+    aes_on == 0 || (key..key + 16).all(|reg| a.reg(reg) == b.reg(reg))
+}
+
+/// Returns the time it takes to send a frame with the current configuration of the radio
+fn airtime_us(radio: &SimRadio, frame_len: usize) -> u64 {
+    let bitrate_reg = u16::from_be_bytes([
+        radio.reg(Register::BitrateMsb.addr()),
+        radio.reg(Register::BitrateLsb.addr()),
+    ]);
+    let preamble = u16::from_be_bytes([
+        radio.reg(Register::PreambleMsb.addr()),
+        radio.reg(Register::PreambleLsb.addr()),
+    ]);
+    let config = radio.reg(Register::SyncConfig.addr());
+    let sync = if config & 0x80 != 0 {
+        ((config >> 3) & 0x07) + 1
+    } else {
+        0
+    };
+    let crc = if radio.is_crc_on() { 2 } else { 0 };
+    let bits = (u64::from(preamble) + u64::from(sync) + frame_len as u64 + crc) * 8;
+    // One bit takes bitrate_reg / 32 MHz
+    bits * u64::from(bitrate_reg) / 32
+}
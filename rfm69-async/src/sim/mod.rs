@@ -0,0 +1,336 @@
+//! Simulated rfm69 transceiver for host side tests
+//!
+//! The simulation models the register map of the rfm69 and implements the hal traits the driver
+//! needs, so `Rfm69` can be used without hardware:
+//!
+//! ```ignore
+//! let radio = RefCell::new(SimRadio::new());
+//! let mut rfm = Rfm69::new(SimSpi::new(&radio), SimReset::new(&radio), Some(SimDio::dio0(&radio)), SimDelay::new(&radio));
+//! rfm.reset().await?;
+//! ```
+//!
+//! Several radios can share an `Air`, which routes the transmitted frames between them in simulated
+//! time.
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embedded_hal_1::digital::{self, InputPin, OutputPin};
+use embedded_hal_1::spi::{self, Operation};
+use embedded_hal_async::delay::DelayUs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::spi::{SpiDevice, SpiDeviceRead, SpiDeviceWrite};
+
+mod air;
+mod radio;
+
+pub use air::{Air, AirNode, Link};
+pub use radio::{Frame, RxFrame, SimMode, SimRadio, FIFO_SIZE, MAX_FRAME_SIZE};
+
+/// Access to the simulated radio the hal implementations are connected to
+pub trait SimBus {
+    /// Runs `f` with exclusive access to the radio
+    fn with_radio<R>(&self, f: impl FnOnce(&mut SimRadio) -> R) -> R;
+
+    /// Called after the driver accessed the radio via spi or the reset pin
+    fn accessed(&self) {}
+
+    /// Called while the driver waits for a pin of the radio
+    ///
+    /// By default the time advances to the next event of the radio, e.g. the next received byte.
+    /// Returns false, if there is no such event.
+    fn idle(&self) -> bool {
+        self.with_radio(|radio| match radio.next_event_us() {
+            Some(us) => {
+                radio.advance(us);
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Waits for `us` microseconds of simulated time
+    ///
+    /// By default the time of the radio advances by `us` without waiting. Events of the radio
+    /// before the end of the delay happen first, each in its own poll, and the delay completes in
+    /// the poll after the time advanced. So futures waiting for an event (e.g. a dio pin raced
+    /// against a timeout) see it before the delay completes.
+    async fn delay_us(&self, us: u32) {
+        let end_us = self.with_radio(|radio| radio.now_us()) + u64::from(us);
+        poll_fn(|cx| {
+            self.with_radio(|radio| {
+                let left_us = end_us.saturating_sub(radio.now_us());
+                if left_us == 0 {
+                    return Poll::Ready(());
+                }
+                let step_us = radio
+                    .next_event_us()
+                    .map_or(left_us, |event_us| event_us.clamp(1, left_us));
+                radio.elapse(step_us as u32);
+                radio.advance(step_us);
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+        })
+        .await
+    }
+}
+
+impl SimBus for RefCell<SimRadio> {
+    fn with_radio<R>(&self, f: impl FnOnce(&mut SimRadio) -> R) -> R {
+        f(&mut self.borrow_mut())
+    }
+}
+
+/// Error of the simulated hal implementations
+///
+/// The simulation never fails, this only exists to satisfy the hal traits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimError;
+
+impl spi::Error for SimError {
+    fn kind(&self) -> spi::ErrorKind {
+        spi::ErrorKind::Other
+    }
+}
+
+impl digital::Error for SimError {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+/// Simulated spi device (including cs) of the radio
+pub struct SimSpi<'a, B: SimBus> {
+    bus: &'a B,
+}
+
+impl<'a, B: SimBus> SimSpi<'a, B> {
+    pub fn new(bus: &'a B) -> Self {
+        Self { bus }
+    }
+}
+
+impl<'a, B: SimBus> spi::ErrorType for SimSpi<'a, B> {
+    type Error = SimError;
+}
+
+impl<'a, B: SimBus> SimSpi<'a, B> {
+    /// Runs `f` with a function clocking one byte through the radio during a single transaction
+    fn clocked(&mut self, f: impl FnOnce(&mut dyn FnMut(u8) -> u8)) {
+        self.bus.with_radio(|radio| {
+            // First byte of a transaction is the address including the write bit
+            let mut addr: Option<u8> = None;
+            let mut write = false;
+            let mut clock = |mosi: u8| -> u8 {
+                match addr {
+                    None => {
+                        addr = Some(mosi & 0x7f);
+                        write = mosi & 0x80 != 0;
+                        0
+                    }
+                    Some(a) => {
+                        let miso = if write {
+                            radio.write(a, mosi);
+                            0
+                        } else {
+                            radio.read(a)
+                        };
+                        // The fifo address is not incremented during burst access
+                        if a != 0 {
+                            addr = Some((a + 1) & 0x7f);
+                        }
+                        miso
+                    }
+                }
+            };
+            f(&mut clock);
+        });
+        self.bus.accessed();
+    }
+}
+
+impl<'a, B: SimBus> SpiDeviceRead<u8> for SimSpi<'a, B> {
+    async fn read_transaction(&mut self, operations: &mut [&mut [u8]]) -> Result<(), Self::Error> {
+        self.clocked(|clock| {
+            for buf in operations.iter_mut() {
+                buf.iter_mut().for_each(|b| *b = clock(0));
+            }
+        });
+        Ok(())
+    }
+}
+
+impl<'a, B: SimBus> SpiDeviceWrite<u8> for SimSpi<'a, B> {
+    async fn write_transaction(&mut self, operations: &[&[u8]]) -> Result<(), Self::Error> {
+        self.clocked(|clock| {
+            for buf in operations.iter() {
+                buf.iter().for_each(|b| {
+                    clock(*b);
+                });
+            }
+        });
+        Ok(())
+    }
+}
+
+impl<'a, B: SimBus> SpiDevice<u8> for SimSpi<'a, B> {
+    async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.clocked(|clock| {
+            for operation in operations.iter_mut() {
+                match operation {
+                    Operation::Read(buf) => buf.iter_mut().for_each(|b| *b = clock(0)),
+                    Operation::Write(buf) => buf.iter().for_each(|b| {
+                        clock(*b);
+                    }),
+                    Operation::Transfer(read, write) => {
+                        for i in 0..read.len().max(write.len()) {
+                            let miso = clock(write.get(i).copied().unwrap_or(0));
+                            if let Some(b) = read.get_mut(i) {
+                                *b = miso;
+                            }
+                        }
+                    }
+                    Operation::TransferInPlace(buf) => buf.iter_mut().for_each(|b| *b = clock(*b)),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Simulated mcu output connected to the reset pin of the radio
+pub struct SimReset<'a, B: SimBus> {
+    bus: &'a B,
+}
+
+impl<'a, B: SimBus> SimReset<'a, B> {
+    pub fn new(bus: &'a B) -> Self {
+        Self { bus }
+    }
+}
+
+impl<'a, B: SimBus> digital::ErrorType for SimReset<'a, B> {
+    type Error = SimError;
+}
+
+impl<'a, B: SimBus> OutputPin for SimReset<'a, B> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.bus.with_radio(|radio| radio.set_reset(false));
+        self.bus.accessed();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.bus.with_radio(|radio| radio.set_reset(true));
+        self.bus.accessed();
+        Ok(())
+    }
+}
+
+/// Simulated mcu input connected to a dio pin of the radio
+pub struct SimDio<'a, B: SimBus> {
+    bus: &'a B,
+    dio1: bool,
+}
+
+impl<'a, B: SimBus> SimDio<'a, B> {
+    /// Returns the input connected to dio0
+    pub fn dio0(bus: &'a B) -> Self {
+        Self { bus, dio1: false }
+    }
+
+    /// Returns the input connected to dio1
+    pub fn dio1(bus: &'a B) -> Self {
+        Self { bus, dio1: true }
+    }
+
+    fn level(&self, radio: &SimRadio) -> bool {
+        if self.dio1 {
+            radio.dio1()
+        } else {
+            radio.dio0()
+        }
+    }
+}
+
+impl<'a, B: SimBus> digital::ErrorType for SimDio<'a, B> {
+    type Error = SimError;
+}
+
+impl<'a, B: SimBus> InputPin for SimDio<'a, B> {
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.bus.with_radio(|radio| self.level(radio)))
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.is_high()?)
+    }
+}
+
+impl<'a, B: SimBus> SimDio<'a, B> {
+    async fn wait_for_level(&mut self, high: bool) -> Result<(), SimError> {
+        poll_fn(|cx| {
+            if self.bus.with_radio(|radio| self.level(radio)) == high {
+                return Poll::Ready(Ok(()));
+            }
+            if self.bus.idle() {
+                // Poll again after the next event, other futures may wait for it as well
+                cx.waker().wake_by_ref();
+            } else {
+                self.bus.with_radio(|radio| radio.register_waker(cx.waker()));
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl<'a, B: SimBus> Wait for SimDio<'a, B> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false).await?;
+        self.wait_for_level(true).await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true).await?;
+        self.wait_for_level(false).await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        let level = self.is_high()?;
+        self.wait_for_level(!level).await
+    }
+}
+
+/// Simulated delay
+///
+/// Waits in simulated time, see `SimBus::delay_us`.
+pub struct SimDelay<'a, B: SimBus> {
+    bus: &'a B,
+}
+
+impl<'a, B: SimBus> SimDelay<'a, B> {
+    pub fn new(bus: &'a B) -> Self {
+        Self { bus }
+    }
+}
+
+impl<'a, B: SimBus> DelayUs for SimDelay<'a, B> {
+    async fn delay_us(&mut self, us: u32) {
+        self.bus.delay_us(us).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.bus.delay_us(ms.saturating_mul(1000)).await
+    }
+}
@@ -0,0 +1,558 @@
+//! Register level model of a single rfm69 transceiver
+
+use core::task::Waker;
+
+use heapless::{Deque, Vec};
+
+use crate::registers::{AfcFei, IrqFlags1, IrqFlags2, Register};
+
+/// Size of the hardware fifo
+pub const FIFO_SIZE: usize = 66;
+
+/// Largest frame (content of the fifo over the whole packet) the model handles
+pub const MAX_FRAME_SIZE: usize = 256;
+
+/// Amount of received frames that can be pending in a radio
+const RX_QUEUE_SIZE: usize = 8;
+
+/// Amount of transmitted frames that are kept until they are taken
+const TX_QUEUE_SIZE: usize = 8;
+
+/// Expected content of Register::Version
+const VERSION: u8 = 0x24;
+
+// Registers that are not (yet) part of `Register`
+const PAYLOAD_LENGTH: u8 = 0x38;
+
+/// Frame as it is seen in the fifo, e.g. including the length byte in variable length mode
+pub type Frame = Vec<u8, MAX_FRAME_SIZE>;
+
+/// A frame that is waiting to be received
+#[derive(Debug, Clone)]
+pub struct RxFrame {
+    pub data: Frame,
+    pub rssi: i16,
+}
+
+/// Mode bits of `Register::OpMode`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimMode {
+    Sleep,
+    Standby,
+    FreqSyn,
+    Tx,
+    Rx,
+    /// Listen mode, the duty cycle is not simulated, the radio receives like in rx mode
+    Listen,
+}
+
+impl SimMode {
+    fn from_reg(reg: u8) -> Self {
+        if reg & 0x40 != 0 {
+            return Self::Listen;
+        }
+        match (reg >> 2) & 0x07 {
+            0b000 => Self::Sleep,
+            0b010 => Self::FreqSyn,
+            0b011 => Self::Tx,
+            0b100 => Self::Rx,
+            _ => Self::Standby,
+        }
+    }
+}
+
+/// Simulated rfm69 register map
+///
+/// The model is not cycle accurate. Mode changes are ready immediately and a packet is on air as soon
+/// as it is completely written into the fifo in tx mode. A pending frame starts to be received as
+/// soon as the radio enters rx mode, its bytes enter the fifo one by one at the configured bit rate
+/// as the simulated time advances. If the fifo is full when a byte arrives, the byte is lost and
+/// `FifoOverrun` is set.
+pub struct SimRadio {
+    regs: [u8; 0x80],
+    fifo: Deque<u8, FIFO_SIZE>,
+
+    // Sticky irq flags, all other flags are derived from the fifo and mode
+    packet_sent: bool,
+    payload_ready: bool,
+    crc_ok: bool,
+    fifo_overrun: bool,
+
+    // Frame that is currently transmitted
+    tx_frame: Frame,
+    // PaLevel, TestPa1 and TestPa2 when the last frame was sent
+    tx_pa: [u8; 3],
+    // Rest of the frame that is currently received, but did not yet arrive in the fifo
+    rx_rest: Deque<u8, MAX_FRAME_SIZE>,
+    // Time the next byte of the received frame arrives in the fifo
+    rx_next_us: u64,
+
+    rx_queue: Deque<RxFrame, RX_QUEUE_SIZE>,
+    tx_queue: Deque<Frame, TX_QUEUE_SIZE>,
+
+    reset_high: bool,
+    resets: u32,
+    // The radio does not react anymore until it is reset
+    hung: bool,
+    elapsed_us: u64,
+    now_us: u64,
+
+    waker: Option<Waker>,
+}
+
+impl Default for SimRadio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimRadio {
+    /// Returns a radio with the register content after power on reset
+    pub fn new() -> Self {
+        let mut radio = Self {
+            regs: [0; 0x80],
+            fifo: Deque::new(),
+            packet_sent: false,
+            payload_ready: false,
+            crc_ok: false,
+            fifo_overrun: false,
+            tx_frame: Vec::new(),
+            tx_pa: [0; 3],
+            rx_rest: Deque::new(),
+            rx_next_us: 0,
+            rx_queue: Deque::new(),
+            tx_queue: Deque::new(),
+            reset_high: false,
+            resets: 0,
+            hung: false,
+            elapsed_us: 0,
+            now_us: 0,
+            waker: None,
+        };
+        radio.power_on_reset();
+        radio
+    }
+
+    fn power_on_reset(&mut self) {
+        self.regs = [0; 0x80];
+        // Default values from the datasheet, chapter 6
+        self.regs[Register::OpMode.addr() as usize] = 0x04;
+        self.regs[Register::BitrateMsb.addr() as usize] = 0x1a;
+        self.regs[Register::BitrateLsb.addr() as usize] = 0x0b;
+        self.regs[Register::FdevLsb.addr() as usize] = 0x52;
+        self.regs[Register::FrfMsb.addr() as usize] = 0xe4;
+        self.regs[Register::FrfMid.addr() as usize] = 0xc0;
+        self.regs[Register::Version.addr() as usize] = VERSION;
+        self.regs[Register::PaLevel.addr() as usize] = 0x9f;
+        self.regs[Register::Ocp.addr() as usize] = 0x1a;
+        self.regs[Register::Lna.addr() as usize] = 0x08;
+        self.regs[Register::RxBw.addr() as usize] = 0x86;
+        self.regs[Register::AfcBw.addr() as usize] = 0x8a;
+        self.regs[Register::AfcFei.addr() as usize] = 0x10;
+        self.regs[Register::RssiValue.addr() as usize] = 0xff;
+        self.regs[Register::RssiThresh.addr() as usize] = 0xe4;
+        self.regs[Register::PreambleLsb.addr() as usize] = 0x03;
+        self.regs[Register::SyncConfig.addr() as usize] = 0x98;
+        for i in 0..8 {
+            self.regs[Register::SyncValue1.addr() as usize + i] = 0x01;
+        }
+        self.regs[Register::PacketConfig1.addr() as usize] = 0x10;
+        self.regs[PAYLOAD_LENGTH as usize] = 0x40;
+        self.regs[Register::FifoThresh.addr() as usize] = 0x0f;
+        self.regs[Register::PacketConfig2.addr() as usize] = 0x02;
+        self.regs[Register::TestPa1.addr() as usize] = 0x55;
+        self.regs[Register::TestPa2.addr() as usize] = 0x70;
+        self.regs[Register::TestDagc.addr() as usize] = 0x30;
+
+        self.fifo.clear();
+        self.packet_sent = false;
+        self.payload_ready = false;
+        self.crc_ok = false;
+        self.fifo_overrun = false;
+        self.tx_frame.clear();
+        self.rx_rest.clear();
+        self.hung = false;
+    }
+
+    /// Returns the raw content of a register without side effects
+    pub fn reg(&self, addr: u8) -> u8 {
+        match addr {
+            a if a == Register::IrqFlags1.addr() => self.irq_flags1(),
+            a if a == Register::IrqFlags2.addr() => self.irq_flags2(),
+            a => self.regs[(a & 0x7f) as usize],
+        }
+    }
+
+    /// Sets the raw content of a register without side effects
+    pub fn set_reg(&mut self, addr: u8, value: u8) {
+        self.regs[(addr & 0x7f) as usize] = value;
+    }
+
+    /// Returns the current mode of the radio
+    pub fn mode(&self) -> SimMode {
+        SimMode::from_reg(self.regs[Register::OpMode.addr() as usize])
+    }
+
+    /// Returns true if the radio is in rx or listen mode
+    pub fn is_receiving(&self) -> bool {
+        matches!(self.mode(), SimMode::Rx | SimMode::Listen)
+    }
+
+    /// Returns the amount of hardware resets through the reset pin
+    pub fn resets(&self) -> u32 {
+        self.resets
+    }
+
+    /// Lets the radio hang until it is reset with the reset pin
+    ///
+    /// A hanging radio never signals ModeReady and does not send frames.
+    pub fn hang(&mut self) {
+        self.hung = true;
+    }
+
+    /// Returns the sum of all delays the driver waited
+    pub fn elapsed_us(&self) -> u64 {
+        self.elapsed_us
+    }
+
+    /// Returns the simulated time of the radio
+    pub fn now_us(&self) -> u64 {
+        self.now_us
+    }
+
+    /// Returns the bytes currently stored in the fifo
+    pub fn fifo_len(&self) -> usize {
+        self.fifo.len()
+    }
+
+    /// Queues a frame that is received the next time the radio is in rx mode
+    ///
+    /// The frame must contain the complete fifo content, e.g. the length byte in variable length mode.
+    /// Returns false if the queue is full.
+    pub fn push_rx(&mut self, data: &[u8], rssi: i16) -> bool {
+        let frame = RxFrame {
+            data: Vec::from_slice(&data[..data.len().min(MAX_FRAME_SIZE)]).unwrap(),
+            rssi,
+        };
+        if self.rx_queue.push_back(frame).is_err() {
+            return false;
+        }
+        self.start_rx();
+        true
+    }
+
+    /// Returns the oldest frame that was sent by the driver
+    pub fn pop_tx(&mut self) -> Option<Frame> {
+        self.tx_queue.pop_front()
+    }
+
+    /// Returns PaLevel, TestPa1 and TestPa2 as they were when the last frame was sent
+    pub fn tx_pa(&self) -> [u8; 3] {
+        self.tx_pa
+    }
+
+    /// Returns the level of the dio0 pin depending on mode and dio mapping
+    pub fn dio0(&self) -> bool {
+        let mapping = self.regs[Register::DioMapping1.addr() as usize] >> 6;
+        match (self.mode(), mapping) {
+            (SimMode::Rx | SimMode::Listen, 0b00) => self.crc_ok,
+            (SimMode::Rx | SimMode::Listen, 0b01) => self.payload_ready,
+            (SimMode::Tx, 0b00) => self.packet_sent,
+            (SimMode::Tx, 0b01) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the level of the dio1 pin depending on mode and dio mapping
+    pub fn dio1(&self) -> bool {
+        let mapping = (self.regs[Register::DioMapping1.addr() as usize] >> 4) & 0x03;
+        let flags = self.irq_flags2();
+        match (self.mode(), mapping) {
+            (SimMode::Sleep, _) => false,
+            (_, 0b00) => flags & IrqFlags2::FifoLevel != 0,
+            (_, 0b01) => flags & IrqFlags2::FifoFull != 0,
+            (_, 0b10) => flags & IrqFlags2::FifoNotEmpty != 0,
+            (SimMode::Tx, 0b11) => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn set_reset(&mut self, high: bool) {
+        if high && !self.reset_high {
+            self.resets += 1;
+            self.power_on_reset();
+            self.wake();
+        }
+        self.reset_high = high;
+    }
+
+    pub(crate) fn elapse(&mut self, us: u32) {
+        self.elapsed_us += u64::from(us);
+    }
+
+    /// Advances the simulated time of the radio, bytes of the received frame arrive in the fifo
+    pub(crate) fn advance(&mut self, us: u64) {
+        self.now_us += us;
+        let mut changed = false;
+        while !self.rx_rest.is_empty() && self.rx_next_us <= self.now_us {
+            if let Some(byte) = self.rx_rest.pop_front() {
+                if self.fifo.push_back(byte).is_err() {
+                    self.fifo_overrun = true;
+                }
+            }
+            self.rx_next_us += self.byte_us();
+            changed = true;
+        }
+        if changed {
+            if self.rx_rest.is_empty() {
+                self.payload_ready = true;
+                self.crc_ok = self.is_crc_on();
+            }
+            self.wake();
+        }
+    }
+
+    /// Returns the time until the next byte of the received frame arrives
+    pub(crate) fn next_event_us(&self) -> Option<u64> {
+        (!self.rx_rest.is_empty()).then(|| self.rx_next_us.saturating_sub(self.now_us))
+    }
+
+    /// Returns the time it takes to receive a single byte with the configured bit rate
+    pub(crate) fn byte_us(&self) -> u64 {
+        let bitrate_reg = u16::from_be_bytes([
+            self.regs[Register::BitrateMsb.addr() as usize],
+            self.regs[Register::BitrateLsb.addr() as usize],
+        ]);
+        // The bit rate is FXOSC (32 MHz) / bitrate_reg
+        (u64::from(bitrate_reg) / 4).max(1)
+    }
+
+    /// Drops the frame that is currently received, e.g. because another frame collided with it
+    pub(crate) fn abort_rx(&mut self) {
+        if !self.rx_rest.is_empty() {
+            self.rx_rest.clear();
+            self.fifo.clear();
+            self.start_rx();
+            self.wake();
+        }
+    }
+
+    pub(crate) fn register_waker(&mut self, waker: &Waker) {
+        self.waker = Some(waker.clone());
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn irq_flags1(&self) -> u8 {
+        if self.hung {
+            return 0;
+        }
+        let mut reg = IrqFlags1::ModeReady as u8;
+        match self.mode() {
+            SimMode::Tx => reg |= IrqFlags1::TxReady as u8 | IrqFlags1::PllLock as u8,
+            SimMode::Rx | SimMode::Listen => reg |= IrqFlags1::RxReady as u8 | IrqFlags1::PllLock as u8,
+            SimMode::FreqSyn => reg |= IrqFlags1::PllLock as u8,
+            _ => (),
+        }
+        reg
+    }
+
+    fn irq_flags2(&self) -> u8 {
+        let threshold = (self.regs[Register::FifoThresh.addr() as usize] & 0x7f) as usize;
+        let mut reg = 0;
+        if self.fifo.is_full() {
+            reg |= IrqFlags2::FifoFull as u8;
+        }
+        if !self.fifo.is_empty() {
+            reg |= IrqFlags2::FifoNotEmpty as u8;
+        }
+        if self.fifo.len() > threshold {
+            reg |= IrqFlags2::FifoLevel as u8;
+        }
+        if self.fifo_overrun {
+            reg |= IrqFlags2::FifoOverrun as u8;
+        }
+        if self.packet_sent {
+            reg |= IrqFlags2::PacketSent as u8;
+        }
+        if self.payload_ready {
+            reg |= IrqFlags2::PayloadReady as u8;
+        }
+        if self.crc_ok {
+            reg |= IrqFlags2::CrcOk as u8;
+        }
+        reg
+    }
+
+    fn is_variable_length(&self) -> bool {
+        self.regs[Register::PacketConfig1.addr() as usize] & 0x80 != 0
+    }
+
+    pub(crate) fn is_crc_on(&self) -> bool {
+        self.regs[Register::PacketConfig1.addr() as usize] & 0x10 != 0
+    }
+
+    fn payload_length(&self) -> usize {
+        self.regs[PAYLOAD_LENGTH as usize] as usize
+    }
+
+    /// Applies the address filtering to the first byte after the length byte
+    fn is_address_accepted(&self, frame: &[u8]) -> bool {
+        let address = if self.is_variable_length() {
+            frame.get(1)
+        } else {
+            frame.first()
+        };
+        let node = self.regs[Register::NodeAdrs.addr() as usize];
+        let broadcast = self.regs[Register::BroadcastAdrs.addr() as usize];
+        match (self.regs[Register::PacketConfig1.addr() as usize] >> 1) & 0x03 {
+            0b00 => true,
+            0b01 => address == Some(&node),
+            _ => address == Some(&node) || address == Some(&broadcast),
+        }
+    }
+
+    /// Reads a register like the spi interface does, including side effects
+    pub(crate) fn read(&mut self, addr: u8) -> u8 {
+        if addr == Register::Fifo.addr() {
+            let byte = self.fifo.pop_front().unwrap_or(0);
+            if self.fifo.is_empty() && self.rx_rest.is_empty() {
+                self.payload_ready = false;
+                self.crc_ok = false;
+            }
+            byte
+        } else {
+            self.reg(addr)
+        }
+    }
+
+    /// Writes a register like the spi interface does, including side effects
+    pub(crate) fn write(&mut self, addr: u8, value: u8) {
+        match addr {
+            a if a == Register::Fifo.addr() => {
+                if self.fifo.push_back(value).is_err() {
+                    self.fifo_overrun = true;
+                }
+                if self.mode() == SimMode::Tx {
+                    self.transmit();
+                }
+            }
+            a if a == Register::OpMode.addr() => {
+                let previous = self.mode();
+                // ListenAbort is never stored, it is only valid together with ListenOn = 0
+                self.regs[a as usize] = value & !0x20;
+                self.enter_mode(previous);
+            }
+            a if a == Register::IrqFlags1.addr() || a == Register::Version.addr() => (),
+            a if a == Register::AfcFei.addr() => {
+                // Measurements are done immediately, the results are set with `set_reg`
+                let mut reg = (self.regs[a as usize] & 0x50) | (value & 0x0c);
+                if value & AfcFei::FeiStart != 0 {
+                    reg |= AfcFei::FeiDone as u8;
+                }
+                if value & AfcFei::AfcStart != 0 {
+                    reg |= AfcFei::AfcDone as u8;
+                }
+                if value & AfcFei::AfcClear != 0 {
+                    self.regs[Register::AfcMsb.addr() as usize] = 0;
+                    self.regs[Register::AfcLsb.addr() as usize] = 0;
+                }
+                self.regs[a as usize] = reg;
+            }
+            a if a == Register::IrqFlags2.addr() => {
+                if value & IrqFlags2::FifoOverrun != 0 {
+                    self.fifo.clear();
+                    self.fifo_overrun = false;
+                }
+            }
+            a => self.regs[a as usize] = value,
+        }
+    }
+
+    fn enter_mode(&mut self, previous: SimMode) {
+        let mode = self.mode();
+        if previous == SimMode::Tx && mode != SimMode::Tx {
+            self.packet_sent = false;
+            self.tx_frame.clear();
+        }
+        if !self.is_receiving() && !self.rx_rest.is_empty() {
+            // Leaving rx aborts the frame that is currently received
+            self.rx_rest.clear();
+            self.fifo.clear();
+        }
+        match mode {
+            SimMode::Tx => self.transmit(),
+            SimMode::Rx | SimMode::Listen => self.start_rx(),
+            _ => (),
+        }
+        self.wake();
+    }
+
+    /// Moves the fifo content onto the air, until the frame is complete
+    fn transmit(&mut self) {
+        if self.packet_sent || self.hung {
+            return;
+        }
+        while let Some(byte) = self.fifo.pop_front() {
+            if self.tx_frame.push(byte).is_err() {
+                break;
+            }
+        }
+        let expected = if self.is_variable_length() {
+            match self.tx_frame.first() {
+                Some(len) => *len as usize + 1,
+                None => return,
+            }
+        } else {
+            self.payload_length()
+        };
+        if expected > 0 && self.tx_frame.len() >= expected {
+            let mut frame = core::mem::take(&mut self.tx_frame);
+            frame.truncate(expected);
+            if self.tx_queue.is_full() {
+                self.tx_queue.pop_front();
+            }
+            let _ = self.tx_queue.push_back(frame);
+            self.packet_sent = true;
+            self.tx_pa = [
+                self.regs[Register::PaLevel.addr() as usize],
+                self.regs[Register::TestPa1.addr() as usize],
+                self.regs[Register::TestPa2.addr() as usize],
+            ];
+            self.wake();
+        }
+    }
+
+    /// Starts receiving the next pending frame, if the radio is ready for it
+    fn start_rx(&mut self) {
+        if !self.is_receiving() || self.payload_ready || !self.rx_rest.is_empty() {
+            return;
+        }
+        while let Some(frame) = self.rx_queue.pop_front() {
+            // In variable length mode the radio drops frames that exceed the payload length
+            let len = if self.is_variable_length() {
+                match frame.data.first() {
+                    Some(len) if (*len as usize) <= self.payload_length() => *len as usize + 1,
+                    _ => continue,
+                }
+            } else {
+                self.payload_length()
+            };
+            if frame.data.len() < len || !self.is_address_accepted(&frame.data) {
+                continue;
+            }
+            self.fifo.clear();
+            for byte in &frame.data[..len] {
+                let _ = self.rx_rest.push_back(*byte);
+            }
+            self.regs[Register::RssiValue.addr() as usize] = (-frame.rssi * 2).clamp(0, 255) as u8;
+            self.rx_next_us = self.now_us + self.byte_us();
+            self.wake();
+            return;
+        }
+    }
+}
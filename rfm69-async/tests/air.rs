@@ -0,0 +1,418 @@
+//! Tests with several simulated radios sharing a deterministic medium
+#![cfg(feature = "sim")]
+
+use core::future::Future;
+
+use embedded_hal_async::delay::DelayUs;
+use futures::executor::block_on;
+use futures::future::{join, join3, select, Either};
+use futures::pin_mut;
+use rfm69_async::config::{RadioHeadModem, Rfm69Config};
+use rfm69_async::mac::{DelayTimer, Mac, MacConfig, TxError};
+use rfm69_async::sim::{Air, AirNode, Link, SimDelay, SimDio, SimReset, SimSpi};
+use rfm69_async::{config, Address, Flags, FrameFormat, Packet, Rfm69};
+
+const NETWORK_ID: u8 = 1;
+
+type SimRfm69<'a, const N: usize> = Rfm69<
+    SimSpi<'a, AirNode<'a, N>>,
+    SimReset<'a, AirNode<'a, N>>,
+    SimDio<'a, AirNode<'a, N>>,
+    SimDelay<'a, AirNode<'a, N>>,
+>;
+
+async fn rfm<'a, const N: usize>(node: &'a AirNode<'a, N>) -> SimRfm69<'a, N> {
+    let rfm = Rfm69::new(
+        SimSpi::new(node),
+        SimReset::new(node),
+        Some(SimDio::dio0(node)),
+        SimDelay::new(node),
+    );
+    config::my_defaults(rfm, NETWORK_ID, 868_000_000, None).await.unwrap()
+}
+
+/// Returns a radio with dio0 and dio1 connected, so it receives packets larger than the fifo
+async fn rfm_dio1<'a, const N: usize>(node: &'a AirNode<'a, N>) -> SimRfm69<'a, N> {
+    let rfm = Rfm69::new(
+        SimSpi::new(node),
+        SimReset::new(node),
+        Some(SimDio::dio0(node)),
+        SimDelay::new(node),
+    )
+    .with_dio1(SimDio::dio1(node));
+    config::my_defaults(rfm, NETWORK_ID, 868_000_000, None).await.unwrap()
+}
+
+type SimMac<'a, const N: usize> = Mac<
+    SimSpi<'a, AirNode<'a, N>>,
+    SimReset<'a, AirNode<'a, N>>,
+    SimDio<'a, AirNode<'a, N>>,
+    SimDelay<'a, AirNode<'a, N>>,
+    DelayTimer<SimDelay<'a, AirNode<'a, N>>, &'a Air<N>>,
+>;
+
+async fn mac<'a, const N: usize>(air: &'a Air<N>, node: &'a AirNode<'a, N>, address: u16) -> SimMac<'a, N> {
+    Mac::new(
+        rfm(node).await,
+        DelayTimer::new(SimDelay::new(node), air),
+        NETWORK_ID,
+        address,
+    )
+}
+
+/// Returns the mac of a node, that talks to RadioHead nodes using `RHReliableDatagram`
+async fn radio_head_mac<'a, const N: usize>(air: &'a Air<N>, node: &'a AirNode<'a, N>, address: u16) -> SimMac<'a, N> {
+    let rfm = Rfm69::new(
+        SimSpi::new(node),
+        SimReset::new(node),
+        Some(SimDio::dio0(node)),
+        SimDelay::new(node),
+    )
+    .with_codec(FrameFormat::RadioHead);
+    let rfm = config::radio_head_defaults(rfm, RadioHeadModem::FskRb125Fd125, 868_000_000, None)
+        .await
+        .unwrap();
+    Mac::new(rfm, DelayTimer::new(SimDelay::new(node), air), NETWORK_ID, address).with_config(MacConfig::radio_head())
+}
+
+fn packet(src: u16, dst: u16, data: &[u8]) -> Packet {
+    Packet::new(Address::Unicast(src), Address::Unicast(dst), Flags::new(), data).unwrap()
+}
+
+/// Runs `scenario` while the simulated time of `air` advances
+fn simulate<const N: usize>(air: &Air<N>, scenario: impl Future<Output = ()>) {
+    pin_mut!(scenario);
+    let run = air.run();
+    pin_mut!(run);
+    block_on(select(scenario, run));
+}
+
+/// Returns the received packet, or `None` if nothing was received within `ms`
+async fn recv_within<const N: usize>(rfm: &mut SimRfm69<'_, N>, node: &AirNode<'_, N>, ms: u32) -> Option<Packet> {
+    let receive = rfm.recv();
+    pin_mut!(receive);
+    let mut delay = SimDelay::new(node);
+    let timeout = delay.delay_ms(ms);
+    pin_mut!(timeout);
+    match select(receive, timeout).await {
+        Either::Left((packet, _)) => Some(packet.unwrap()),
+        Either::Right(_) => None,
+    }
+}
+
+#[test]
+fn frame_is_delivered() {
+    let air: Air<2> = Air::new(1);
+    let (n0, n1) = (air.node(0), air.node(1));
+    simulate(&air, async {
+        let mut a = rfm(&n0).await;
+        let mut b = rfm(&n1).await;
+        let (sent, received) = join(a.send(&packet(1, 2, &[1, 2, 3])), recv_within(&mut b, &n1, 100)).await;
+        sent.unwrap();
+        let received = received.unwrap();
+        assert_eq!(received.src, Address::Unicast(1));
+        assert_eq!(received.dst, Address::Unicast(2));
+        assert_eq!(&received.data[..], &[1, 2, 3]);
+        assert_eq!(received.rssi, Some(-60));
+        // 3 bytes preamble, 2 sync words, 7 bytes and crc take about 1.1 ms at 100 kbit/s
+        assert!(air.now_us() > 1_000);
+    });
+}
+
+#[test]
+fn lost_frame_is_resent() {
+    let air: Air<2> = Air::new(2);
+    let (n0, n1) = (air.node(0), air.node(1));
+    air.set_link(0, 1, Link::disconnected());
+    simulate(&air, async {
+        let mut a = rfm(&n0).await;
+        let mut b = rfm(&n1).await;
+        let (sent, received) = join(a.send(&packet(1, 2, &[4])), recv_within(&mut b, &n1, 100)).await;
+        sent.unwrap();
+        assert!(received.is_none());
+
+        air.set_link(0, 1, Link::default());
+        let (sent, received) = join(a.send(&packet(1, 2, &[4])), recv_within(&mut b, &n1, 100)).await;
+        sent.unwrap();
+        assert_eq!(&received.unwrap().data[..], &[4]);
+    });
+}
+
+#[test]
+fn corrupted_frame_fails_crc() {
+    let air: Air<2> = Air::new(3);
+    let (n0, n1) = (air.node(0), air.node(1));
+    air.set_link(
+        0,
+        1,
+        Link {
+            corruption: 100,
+            ..Default::default()
+        },
+    );
+    simulate(&air, async {
+        let mut a = rfm(&n0).await;
+        let mut b = rfm(&n1).await;
+        let (sent, received) = join(a.send(&packet(1, 2, &[5; 10])), recv_within(&mut b, &n1, 100)).await;
+        sent.unwrap();
+        assert!(received.is_none());
+    });
+}
+
+#[test]
+fn overlapping_frames_collide() {
+    let air: Air<3> = Air::new(4);
+    let (n0, n1, n2) = (air.node(0), air.node(1), air.node(2));
+    simulate(&air, async {
+        let mut a = rfm(&n0).await;
+        let mut b = rfm(&n1).await;
+        let mut c = rfm(&n2).await;
+        let (p1, p3) = (packet(1, 2, &[7; 8]), packet(3, 2, &[8; 8]));
+        let send = join(a.send(&p1), c.send(&p3));
+        let (sent, received) = join(send, recv_within(&mut b, &n1, 100)).await;
+        sent.0.unwrap();
+        sent.1.unwrap();
+        assert!(received.is_none());
+    });
+}
+
+#[test]
+fn other_network_is_not_received() {
+    let air: Air<2> = Air::new(5);
+    let (n0, n1) = (air.node(0), air.node(1));
+    simulate(&air, async {
+        let mut a = rfm(&n0).await;
+        let mut b = rfm(&n1).await;
+        b.sync(&[0x2d, NETWORK_ID + 1]).await.unwrap();
+        let (sent, received) = join(a.send(&packet(1, 2, &[9])), recv_within(&mut b, &n1, 100)).await;
+        sent.unwrap();
+        assert!(received.is_none());
+    });
+}
+
+#[test]
+fn frame_for_other_node_is_filtered_by_radio() {
+    let air: Air<3> = Air::new(14);
+    let (n0, n1, n2) = (air.node(0), air.node(1), air.node(2));
+    simulate(&air, async {
+        let mut a = rfm(&n0).await;
+        let mut b = mac(&air, &n1, 2).await;
+        b.apply(&Rfm69Config::my_defaults(NETWORK_ID, 868_000_000))
+            .await
+            .unwrap();
+        let mut c = rfm(&n2).await;
+
+        // The radio of b drops the frame for 3, the driver of b does not see it at all
+        let (sent, (dropped, received)) = join(
+            a.send(&packet(1, 3, &[1])),
+            join(recv_within(b.rfm(), &n1, 100), recv_within(&mut c, &n2, 100)),
+        )
+        .await;
+        sent.unwrap();
+        assert!(dropped.is_none());
+        assert_eq!(&received.unwrap().data[..], &[1]);
+
+        // Frames for b and broadcasts pass the filter
+        let (sent, received) = join(a.send(&packet(1, 2, &[2])), recv_within(b.rfm(), &n1, 100)).await;
+        sent.unwrap();
+        assert_eq!(&received.unwrap().data[..], &[2]);
+        let broadcast = Packet::new(Address::Unicast(1), Address::Broadcast, Flags::new(), &[3]).unwrap();
+        let (sent, received) = join(a.send(&broadcast), recv_within(b.rfm(), &n1, 100)).await;
+        sent.unwrap();
+        assert_eq!(&received.unwrap().data[..], &[3]);
+    });
+}
+
+#[test]
+fn long_frame_is_streamed() {
+    let air: Air<3> = Air::new(6);
+    let (n0, n1, n2) = (air.node(0), air.node(1), air.node(2));
+    simulate(&air, async {
+        let mut a = rfm_dio1(&n0).await;
+        let mut b = rfm_dio1(&n1).await;
+        // Without dio1 the payload length is limited to the fifo, so the frame is dropped
+        let mut c = rfm(&n2).await;
+        let data: [u8; 150] = core::array::from_fn(|i| i as u8);
+        let sent = packet(1, 2, &data);
+        let start = air.now_us();
+        let (result, (received, dropped)) = join(
+            a.send(&sent),
+            join(recv_within(&mut b, &n1, 100), recv_within(&mut c, &n2, 100)),
+        )
+        .await;
+        result.unwrap();
+        assert_eq!(&received.unwrap().data[..], &data);
+        assert!(dropped.is_none());
+        // 154 bytes take more than 12 ms at 100 kbit/s
+        assert!(air.now_us() - start > 12_000);
+    });
+}
+
+#[test]
+fn reliable_send_is_acked() {
+    let air: Air<2> = Air::new(7);
+    let (n0, n1) = (air.node(0), air.node(1));
+    simulate(&air, async {
+        let mut a = mac(&air, &n0, 1).await;
+        let mut b = mac(&air, &n1, 2).await;
+        let start = air.now_us();
+        let (sent, received) = join(a.send_reliable(Address::Unicast(2), &[1, 2, 3]), b.recv()).await;
+        sent.unwrap();
+        let packet = received.unwrap();
+        assert_eq!(packet.src, Address::Unicast(1));
+        assert_eq!(&packet.data[..], &[1, 2, 3]);
+        assert!(air.now_us() - start < u64::from(a.config().ack_timeout_us));
+    });
+}
+
+#[test]
+fn reliable_send_retries_after_dropped_frame() {
+    let air: Air<2> = Air::new(8);
+    let (n0, n1) = (air.node(0), air.node(1));
+    air.set_link(0, 1, Link::disconnected());
+    simulate(&air, async {
+        let mut a = mac(&air, &n0, 1).await;
+        let mut b = mac(&air, &n1, 2).await;
+        let start = air.now_us();
+        let ack_timeout_us = a.config().ack_timeout_us;
+        // The first frame is lost, the link recovers before the retry
+        let reconnect = async {
+            SimDelay::new(&n0).delay_us(ack_timeout_us / 2).await;
+            air.set_link(0, 1, Link::default());
+        };
+        let (sent, received, _) = join3(a.send_reliable(Address::Unicast(2), &[4, 5]), b.recv(), reconnect).await;
+        sent.unwrap();
+        assert_eq!(&received.unwrap().data[..], &[4, 5]);
+        assert!(air.now_us() - start >= u64::from(ack_timeout_us));
+        assert_eq!(b.state().duplicates(), 0);
+    });
+}
+
+#[test]
+fn reliable_send_times_out_without_ack() {
+    let air: Air<2> = Air::new(9);
+    let (n0, n1) = (air.node(0), air.node(1));
+    // Frames reach node 1, but its acks are lost
+    air.set_link(1, 0, Link::disconnected());
+    simulate(&air, async {
+        let mut a = mac(&air, &n0, 1).await;
+        let mut b = mac(&air, &n1, 2).await;
+        let start = air.now_us();
+        let config = *a.config();
+        let sent = a.send_reliable(Address::Unicast(2), &[6]);
+        pin_mut!(sent);
+        let receive = async {
+            let packet = b.recv().await.unwrap();
+            assert_eq!(&packet.data[..], &[6]);
+            // Repeated frames are acked again, but not delivered
+            b.recv().await.unwrap();
+            unreachable!("repeated frame was delivered");
+        };
+        pin_mut!(receive);
+        match select(sent, receive).await {
+            Either::Left((result, _)) => assert!(matches!(result, Err(TxError::AckTimeout))),
+            Either::Right(_) => unreachable!(),
+        }
+        let attempts = u64::from(config.retries);
+        assert!(air.now_us() - start >= attempts * u64::from(config.ack_timeout_us));
+    });
+}
+
+#[test]
+fn every_packet_uses_a_sequence_number() {
+    let air: Air<2> = Air::new(10);
+    let (n0, n1) = (air.node(0), air.node(1));
+    simulate(&air, async {
+        let mut a = mac(&air, &n0, 1).await.with_initial_seq(20);
+        let mut b = mac(&air, &n1, 2).await;
+        let (sent, first) = join(a.send(Address::Unicast(2), &[1]), b.recv()).await;
+        sent.unwrap();
+        let (sent, second) = join(a.send(Address::Unicast(2), &[2]), b.recv()).await;
+        sent.unwrap();
+        let (sent, third) = join(a.send_reliable(Address::Unicast(2), &[3]), b.recv()).await;
+        sent.unwrap();
+        assert_eq!(first.unwrap().seq, Some(21));
+        assert_eq!(second.unwrap().seq, Some(22));
+        assert_eq!(third.unwrap().seq, Some(23));
+    });
+}
+
+#[test]
+fn sequence_numbers_expire() {
+    let air: Air<2> = Air::new(11);
+    let (n0, n1) = (air.node(0), air.node(1));
+    simulate(&air, async {
+        let mut a = mac(&air, &n0, 1).await.with_initial_seq(9);
+        let mut b = mac(&air, &n1, 2).await;
+        let (sent, received) = join(a.send_reliable(Address::Unicast(2), &[1]), b.recv()).await;
+        sent.unwrap();
+        assert_eq!(received.unwrap().seq, Some(10));
+
+        // A restarted sender repeats the sequence number, after the receiver forgot it
+        SimDelay::new(&n0).delay_ms(11_000).await;
+        let mut a = a.with_initial_seq(9);
+        let packet = {
+            let receive = b.recv();
+            pin_mut!(receive);
+            let send = async {
+                a.send_reliable(Address::Unicast(2), &[2]).await.unwrap();
+                SimDelay::new(&n0).delay_ms(100).await;
+            };
+            pin_mut!(send);
+            match select(receive, send).await {
+                Either::Left((received, _)) => received.unwrap(),
+                Either::Right(_) => panic!("packet was dropped as retransmission"),
+            }
+        };
+        assert_eq!(packet.seq, Some(10));
+        assert_eq!(&packet.data[..], &[2]);
+        assert_eq!(b.state().duplicates(), 0);
+    });
+}
+
+#[test]
+fn radio_head_ack_timeouts_are_randomised() {
+    let air: Air<1> = Air::new(12);
+    let n0 = air.node(0);
+    simulate(&air, async {
+        let mut a = mac(&air, &n0, 1).await.with_config(MacConfig::radio_head());
+        let start = air.now_us();
+        let result = a.send_reliable(Address::Unicast(2), &[1]).await;
+        assert!(matches!(result, Err(TxError::AckTimeout)));
+        // 4 attempts, each waits 200 ms to 400 ms for the ack and is repeated right away
+        let elapsed = air.now_us() - start;
+        assert!((800_000..1_600_000).contains(&elapsed), "{elapsed}");
+    });
+}
+
+#[test]
+fn radio_head_receiver_gets_every_packet() {
+    let air: Air<2> = Air::new(13);
+    let (n0, n1) = (air.node(0), air.node(1));
+    simulate(&air, async {
+        let mut a = radio_head_mac(&air, &n0, 1).await;
+        let mut b = radio_head_mac(&air, &n1, 2).await;
+        // RadioHead drops a unicast frame with the id of the last one from the same sender, even
+        // if no ack was requested
+        let (sent, first) = join(a.send(Address::Unicast(2), &[1]), b.recv()).await;
+        sent.unwrap();
+        let first = first.unwrap();
+        let second = {
+            let receive = b.recv();
+            pin_mut!(receive);
+            let send = async {
+                a.send(Address::Unicast(2), &[2]).await.unwrap();
+                SimDelay::new(&n0).delay_ms(100).await;
+            };
+            pin_mut!(send);
+            match select(receive, send).await {
+                Either::Left((received, _)) => received.unwrap(),
+                Either::Right(_) => panic!("packet was dropped as retransmission"),
+            }
+        };
+        assert_eq!(&first.data[..], &[1]);
+        assert_eq!(&second.data[..], &[2]);
+        assert_eq!(second.seq, first.seq.map(|seq| seq.wrapping_add(1)));
+        assert_eq!(b.state().duplicates(), 0);
+    });
+}
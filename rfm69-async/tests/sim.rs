@@ -0,0 +1,635 @@
+//! Driver tests against a single simulated radio
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use futures::executor::block_on;
+use futures::{pin_mut, FutureExt};
+use rfm69_async::config::{ConfigError, Rfm69Config};
+use rfm69_async::registers::{
+    CrystalOffset, DataMode, DccCutoff, InterPacketRxDelay, ListenConfig, ListenCriteria, ListenEnd, ListenResolution,
+    Modulation, ModulationShaping, ModulationType, OutputPower, PacketConfig, PacketDc, PacketFiltering, PacketFormat,
+    RxBw, RxBwFreq, RxBwFsk, RxBwOok, Timeouts, Variant,
+};
+use rfm69_async::sim::{SimDelay, SimDio, SimMode, SimRadio, SimReset, SimSpi};
+use rfm69_async::{config, Address, Error, Flags, Packet, Raw, RawFrame, RecoveryCause, Rfm69, WaitState};
+
+type SimRfm69<'a> = Rfm69<
+    SimSpi<'a, RefCell<SimRadio>>,
+    SimReset<'a, RefCell<SimRadio>>,
+    SimDio<'a, RefCell<SimRadio>>,
+    SimDelay<'a, RefCell<SimRadio>>,
+>;
+
+fn rfm(radio: &RefCell<SimRadio>) -> SimRfm69<'_> {
+    Rfm69::new(
+        SimSpi::new(radio),
+        SimReset::new(radio),
+        Some(SimDio::dio0(radio)),
+        SimDelay::new(radio),
+    )
+}
+
+#[test]
+fn my_defaults_send() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        assert_eq!(radio.borrow().resets(), 1);
+        assert_eq!(radio.borrow().reg(0x2f), 0x2d);
+        assert_eq!(radio.borrow().reg(0x30), 42);
+
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[1, 2, 3]).unwrap();
+        rfm.send(&packet).await.unwrap();
+        let frame = radio.borrow_mut().pop_tx().unwrap();
+        assert_eq!(&frame[..], &[7, 2, 1, 0x40, 0, 1, 2, 3]);
+        assert!(radio.borrow_mut().pop_tx().is_none());
+    });
+}
+
+#[test]
+fn my_defaults_recv() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        assert!(radio.borrow_mut().push_rx(&[6, 1, 2, 0x40, 0, 9, 8], -70));
+        let packet = rfm.recv().await.unwrap();
+        assert_eq!(packet.src, Address::Unicast(2));
+        assert_eq!(packet.dst, Address::Unicast(1));
+        assert_eq!(&packet.data[..], &[9, 8]);
+        assert_eq!(packet.rssi, Some(-70));
+    });
+}
+
+#[test]
+fn raw_codec_round_trip() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let rfm = rfm(&radio).with_codec(Raw);
+        let mut rfm = config::my_defaults(rfm, 42, 868_000_000, None).await.unwrap();
+
+        // The driver adds the length byte of the variable packet format
+        let frame = RawFrame::new(&[0xde, 0xad, 0xbe, 0xef]).unwrap();
+        rfm.send(&frame).await.unwrap();
+        assert_eq!(&radio.borrow_mut().pop_tx().unwrap()[..], &[4, 0xde, 0xad, 0xbe, 0xef]);
+
+        assert!(radio.borrow_mut().push_rx(&[3, 1, 2, 3], -70));
+        let received = rfm.recv().await.unwrap();
+        assert_eq!(&received.data[..], &[1, 2, 3]);
+        assert_eq!(received.rx.unwrap().rssi, -70);
+    });
+}
+
+#[test]
+fn fixed_length_frames_are_padded() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        rfm.packet(PacketConfig {
+            format: PacketFormat::Fixed(8),
+            dc: PacketDc::None,
+            filtering: PacketFiltering::None,
+            crc: true,
+            interpacket_rx_delay: InterPacketRxDelay::Delay2Bits,
+            auto_rx_restart: true,
+        })
+        .await
+        .unwrap();
+
+        // There is no length byte, the frame is filled up with zeros
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[7, 8]).unwrap();
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(&radio.borrow_mut().pop_tx().unwrap()[..], &[2, 1, 0x40, 0, 7, 8, 0, 0]);
+
+        // A received frame always has the fixed length
+        assert!(radio.borrow_mut().push_rx(&[4, 3, 0x40, 9, 5, 6, 0, 0], -60));
+        let packet = rfm.recv().await.unwrap();
+        assert_eq!(packet.src, Address::Unicast(3));
+        assert_eq!(packet.seq, Some(9));
+        assert_eq!(&packet.data[..], &[5, 6, 0, 0]);
+
+        // A frame longer than the fixed length is rejected before it reaches the radio
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[1, 2, 3, 4, 5]).unwrap();
+        assert!(matches!(rfm.send(&packet).await, Err(Error::PacketSize)));
+        assert!(radio.borrow_mut().pop_tx().is_none());
+    });
+}
+
+#[test]
+fn setters_write_registers() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = rfm(&radio);
+        rfm.reset().await.unwrap();
+
+        rfm.bit_rate(4_800).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x03), 0x1a);
+        assert_eq!(radio.borrow().reg(0x04), 0x0a);
+
+        rfm.fdev(5_000).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x05), 0x00);
+        assert_eq!(radio.borrow().reg(0x06), 0x51);
+
+        rfm.frequency(868_000_000).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x07), 0xd9);
+        assert_eq!(radio.borrow().reg(0x08), 0x00);
+        assert_eq!(radio.borrow().reg(0x09), 0x00);
+
+        rfm.preamble_length(0x1234).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x2c), 0x12);
+        assert_eq!(radio.borrow().reg(0x2d), 0x34);
+
+        rfm.sync(&[0x2d, 0xd4, 0x01]).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x2f), 0x2d);
+        assert_eq!(radio.borrow().reg(0x30), 0xd4);
+        assert_eq!(radio.borrow().reg(0x31), 0x01);
+
+        rfm.packet(PacketConfig {
+            format: PacketFormat::Fixed(20),
+            dc: PacketDc::None,
+            filtering: PacketFiltering::None,
+            crc: true,
+            interpacket_rx_delay: InterPacketRxDelay::Delay2Bits,
+            auto_rx_restart: true,
+        })
+        .await
+        .unwrap();
+        assert_eq!(radio.borrow().reg(0x37), 0x10);
+        assert_eq!(radio.borrow().reg(0x38), 20);
+
+        rfm.rssi_threshold(0xc8).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x29), 0xc8);
+    });
+}
+
+#[test]
+fn fdev_out_of_range_is_rejected() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = rfm(&radio);
+        rfm.reset().await.unwrap();
+        assert_eq!(rfm.fdev(50_000).await.unwrap(), 49_987);
+        // 1 MHz does not fit into the 14 bit register
+        assert!(matches!(rfm.fdev(1_000_000).await, Err(Error::FdevRange)));
+        assert!(matches!(rfm.fdev(5_000_000).await, Err(Error::FdevRange)));
+        assert_eq!(radio.borrow().reg(0x05), 0x03);
+        assert_eq!(radio.borrow().reg(0x06), 0x33);
+    });
+}
+
+#[test]
+fn crystal_offset_is_compensated() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = rfm(&radio);
+        rfm.reset().await.unwrap();
+        assert!(rfm.frequency(868_000_000).await.unwrap().abs_diff(868_000_000) < 62);
+        assert_eq!(
+            [0x07, 0x08, 0x09].map(|addr| radio.borrow().reg(addr)),
+            [0xd9, 0x00, 0x00]
+        );
+
+        // The crystal is 10 ppm too fast, so less steps are needed
+        let mut rfm = rfm.with_crystal_offset(CrystalOffset::Ppm(10));
+        assert!(rfm.frequency(868_000_000).await.unwrap().abs_diff(868_000_000) < 62);
+        assert_eq!(
+            [0x07, 0x08, 0x09].map(|addr| radio.borrow().reg(addr)),
+            [0xd8, 0xff, 0x71]
+        );
+        assert_eq!(rfm.bit_rate(100_000).await.unwrap(), 100_001);
+
+        let mut rfm = rfm.with_crystal_offset(CrystalOffset::Hz(-320));
+        assert!(rfm.frequency(868_000_000).await.unwrap().abs_diff(868_000_000) < 62);
+        assert_eq!(
+            [0x07, 0x08, 0x09].map(|addr| radio.borrow().reg(addr)),
+            [0xd9, 0x00, 0x8e]
+        );
+    });
+}
+
+#[test]
+fn config_readback() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = rfm(&radio);
+        rfm.reset().await.unwrap();
+
+        let bit_rate = rfm.bit_rate(4_800).await.unwrap();
+        assert_eq!(rfm.read_bit_rate().await.unwrap(), bit_rate);
+        let fdev = rfm.fdev(5_000).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x06), 0x51);
+        assert_eq!(rfm.read_fdev().await.unwrap(), fdev);
+        let frequency = rfm.frequency(868_000_000).await.unwrap();
+        assert_eq!(rfm.read_frequency().await.unwrap(), frequency);
+        rfm.preamble_length(0x1234).await.unwrap();
+        assert_eq!(rfm.read_preamble_length().await.unwrap(), 0x1234);
+        rfm.sync(&[0x2d, 0xd4, 0x01]).await.unwrap();
+        assert_eq!(rfm.read_sync().await.unwrap().as_slice(), &[0x2d, 0xd4, 0x01]);
+        rfm.aes(Some(&[0x5a; 16])).await.unwrap();
+        assert_eq!(rfm.read_aes().await.unwrap(), Some([0x5a; 16]));
+        rfm.aes(None).await.unwrap();
+        assert_eq!(rfm.read_aes().await.unwrap(), None);
+
+        let config = Rfm69Config::my_defaults(42, 868_000_000);
+        rfm.apply(&config).await.unwrap();
+        assert!(rfm.is_configured(&config).await.unwrap());
+        let actual: Rfm69Config<RxBwFsk> = rfm.read_config().await.unwrap();
+        assert_eq!(actual.rx_bw.rx_bw.hz(), 125_000);
+        assert!(!rfm.is_configured(&config.clone().preamble_length(4)).await.unwrap());
+
+        // Ook uses half the bandwidth of fsk for the same register value
+        let ook = config
+            .clone()
+            .modulation(Modulation {
+                data_mode: DataMode::Packet,
+                modulation_type: ModulationType::Ook,
+                shaping: ModulationShaping::Shaping00,
+            })
+            .bit_rate(4_800)
+            .rx_bw(RxBw {
+                dcc_cutoff: DccCutoff::Percent4,
+                rx_bw: RxBwOok::Khz62dot5,
+            });
+        rfm.apply(&ook).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x19), 0x40 | RxBwFsk::Khz125dot0.value());
+        assert!(rfm.is_configured(&ook).await.unwrap());
+        let actual: Rfm69Config<RxBwOok> = rfm.read_config().await.unwrap();
+        assert_eq!(actual.rx_bw.rx_bw.hz(), 62_500);
+        assert!(matches!(
+            rfm.read_config::<RxBwFsk>().await,
+            Err(Error::RegisterValue(0x19))
+        ));
+
+        let mismatch = ook.clone().rx_bw(RxBw {
+            dcc_cutoff: DccCutoff::Percent4,
+            rx_bw: RxBwFsk::Khz125dot0,
+        });
+        assert!(matches!(
+            rfm.apply(&mismatch).await,
+            Err(Error::Config(ConfigError::RxBw))
+        ));
+    });
+}
+
+#[test]
+fn rejected_config_is_not_written() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        let regs: [u8; 0x50] = core::array::from_fn(|addr| radio.borrow().reg(addr as u8));
+        let config = Rfm69Config::my_defaults(7, 915_000_000);
+        assert!(matches!(
+            rfm.apply(&config.clone().tx_power(30)).await,
+            Err(Error::OutputPower)
+        ));
+        assert!(matches!(
+            rfm.apply(&config.clone().tx_power(-19)).await,
+            Err(Error::OutputPower)
+        ));
+        assert!(matches!(
+            rfm.apply(&config.clone().bit_rate(500_000)).await,
+            Err(Error::Config(ConfigError::BitRate))
+        ));
+        assert!(matches!(
+            rfm.apply(&config.clone().frequency(600_000_000)).await,
+            Err(Error::Config(ConfigError::Frequency))
+        ));
+        assert!(matches!(
+            rfm.apply(&config.clone().sync(&[0x2d, 0x00]).unwrap()).await,
+            Err(Error::Config(ConfigError::SyncValue))
+        ));
+        assert_eq!(config.clone().sync(&[0x2d; 9]).err(), Some(ConfigError::SyncLength));
+
+        let mut fixed = config.clone();
+        fixed.packet.format = PacketFormat::Fixed(65);
+        assert!(matches!(
+            rfm.apply(&fixed.clone().aes_key(Some(&[1; 16]))).await,
+            Err(Error::Config(ConfigError::PacketSize))
+        ));
+        // Without dio1 only packets that fit into the fifo are received
+        fixed.packet.format = PacketFormat::Fixed(100);
+        assert!(matches!(rfm.apply(&fixed).await, Err(Error::PacketSize)));
+
+        for (addr, value) in regs.iter().enumerate() {
+            assert_eq!(radio.borrow().reg(addr as u8), *value, "register {addr:#04x}");
+        }
+
+        rfm.apply(&config).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x30), 7);
+    });
+}
+
+#[test]
+fn payload_length_without_dio1() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        // With dio0 only, the driver does not see the fifo level while receiving
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x38), 65);
+        let fixed = |length| PacketConfig {
+            format: PacketFormat::Fixed(length),
+            dc: PacketDc::None,
+            filtering: PacketFiltering::None,
+            crc: true,
+            interpacket_rx_delay: InterPacketRxDelay::Delay2Bits,
+            auto_rx_restart: true,
+        };
+        assert!(matches!(rfm.packet(fixed(100)).await, Err(Error::PacketSize)));
+        assert_eq!(radio.borrow().reg(0x37), 0x90);
+        assert_eq!(radio.borrow().reg(0x38), 65);
+
+        // Long packets are streamed with dio1
+        let rfm = rfm.with_dio1(SimDio::dio1(&radio));
+        let mut rfm = config::my_defaults(rfm, 42, 868_000_000, None).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x38), 255);
+        rfm.packet(fixed(100)).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x38), 100);
+    });
+}
+
+#[test]
+fn long_frames_are_streamed_with_dio1() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let rfm = rfm(&radio).with_dio1(SimDio::dio1(&radio));
+        let mut rfm = config::my_defaults(rfm, 42, 868_000_000, None).await.unwrap();
+
+        let data: [u8; 200] = core::array::from_fn(|i| i as u8);
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &data).unwrap();
+        rfm.send(&packet).await.unwrap();
+        let frame = radio.borrow_mut().pop_tx().unwrap();
+        assert_eq!(frame.len(), 205);
+        assert_eq!(&frame[..5], &[204, 2, 1, 0x40, 0]);
+        assert_eq!(&frame[5..], &data);
+
+        // The frame arrives in the fifo while it is read, at 100 kbit/s a byte takes 80 us
+        assert!(radio.borrow_mut().push_rx(&frame, -50));
+        let start = radio.borrow().now_us();
+        let packet = rfm.recv().await.unwrap();
+        assert_eq!(&packet.data[..], &data);
+        assert!(radio.borrow().now_us() - start >= 205 * 80);
+        assert_eq!(radio.borrow().reg(0x28) & 0x10, 0);
+    });
+}
+
+#[test]
+fn fifo_overrun_is_reported() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        // Without dio1 nobody reads the fifo while a frame larger than the fifo is received
+        radio.borrow_mut().set_reg(0x38, 255);
+        let mut frame = [7; 101];
+        frame[0] = 100;
+        assert!(radio.borrow_mut().push_rx(&frame, -50));
+        assert!(matches!(rfm.recv().await, Err(Error::FifoOverrun)));
+
+        // The next frame is received again
+        assert!(radio.borrow_mut().push_rx(&[6, 2, 1, 0x40, 0, 9, 8], -70));
+        assert_eq!(&rfm.recv().await.unwrap().data[..], &[9, 8]);
+    });
+}
+
+#[test]
+fn aes_limits_packet_length() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let rfm = rfm(&radio).with_dio1(SimDio::dio1(&radio));
+        let key = [0x5a; 16];
+        let mut rfm = config::my_defaults(rfm, 42, 868_000_000, Some(&key)).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x3d) & 0x01, 0x01);
+        assert_eq!(radio.borrow().reg(0x3e), 0x5a);
+
+        // 4 bytes header and 60 bytes data are 64 bytes
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[3; 60]).unwrap();
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(radio.borrow_mut().pop_tx().unwrap().len(), 65);
+
+        let mode = radio.borrow().reg(0x01);
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[3; 61]).unwrap();
+        assert!(matches!(rfm.send(&packet).await, Err(Error::PacketSize)));
+        assert!(radio.borrow_mut().pop_tx().is_none());
+        assert_eq!(radio.borrow().reg(0x01), mode);
+
+        // Without aes the packet is streamed
+        rfm.aes(None).await.unwrap();
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(radio.borrow_mut().pop_tx().unwrap().len(), 66);
+    });
+}
+
+#[test]
+fn listen_recv_restarts_listen_mode() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        rfm.listen(ListenConfig {
+            idle_resolution: ListenResolution::Ms4dot1,
+            idle_coefficient: 245,
+            rx_resolution: ListenResolution::Us64,
+            rx_coefficient: 32,
+            criteria: ListenCriteria::RssiSyncAddress,
+            end: ListenEnd::Rx,
+        })
+        .await
+        .unwrap();
+        assert_eq!(radio.borrow().reg(0x0e), 245);
+        assert_eq!(radio.borrow().reg(0x0f), 32);
+        rfm.start_listen().await.unwrap();
+        assert_eq!(radio.borrow().mode(), SimMode::Listen);
+
+        assert!(radio.borrow_mut().push_rx(&[6, 2, 1, 0x40, 0, 9, 8], -70));
+        assert_eq!(&rfm.listen_recv().await.unwrap().data[..], &[9, 8]);
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+
+        // A failed receive leaves listen mode as well
+        radio.borrow_mut().set_reg(0x38, 255);
+        let mut frame = [7; 101];
+        frame[0] = 100;
+        assert!(radio.borrow_mut().push_rx(&frame, -50));
+        assert!(matches!(rfm.listen_recv().await, Err(Error::FifoOverrun)));
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+
+        assert!(radio.borrow_mut().push_rx(&[6, 2, 1, 0x40, 0, 7, 6], -70));
+        assert_eq!(&rfm.listen_recv().await.unwrap().data[..], &[7, 6]);
+    });
+}
+
+#[test]
+fn rx_timeout_returns_to_standby() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let rfm = rfm(&radio).with_timeouts(Timeouts {
+            rx_us: Some(10_000),
+            ..Default::default()
+        });
+        let mut rfm = config::my_defaults(rfm, 42, 868_000_000, None).await.unwrap();
+        let start = radio.borrow().now_us();
+        assert!(matches!(rfm.recv().await, Err(Error::Timeout(WaitState::PayloadReady))));
+        assert!(radio.borrow().now_us() - start >= 10_000);
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+
+        // Listen mode is entered again after the timeout
+        rfm.start_listen().await.unwrap();
+        assert!(matches!(
+            rfm.listen_recv().await,
+            Err(Error::Timeout(WaitState::PayloadReady))
+        ));
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+        assert!(radio.borrow_mut().push_rx(&[6, 2, 1, 0x40, 0, 3, 4], -70));
+        assert_eq!(&rfm.listen_recv().await.unwrap().data[..], &[3, 4]);
+    });
+}
+
+#[test]
+fn supervisor_recovers_radio() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let mut rfm = rfm(&radio);
+        rfm.reset().await.unwrap();
+        let config = Rfm69Config::my_defaults(42, 868_000_000).bit_rate(50_000);
+        rfm.apply(&config).await.unwrap();
+        assert_eq!(rfm.supervise().await.unwrap(), None);
+        let resets = radio.borrow().resets();
+
+        // A register lost its content, e.g. after a brownout
+        radio.borrow_mut().set_reg(0x03, 0x1a);
+        assert_eq!(rfm.supervise().await.unwrap(), Some(RecoveryCause::ConfigLost));
+        assert_eq!(radio.borrow().resets(), resets + 1);
+        assert_eq!(radio.borrow().reg(0x03), 0x02);
+        assert!(rfm.is_configured(&config).await.unwrap());
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+
+        // The radio left the mode it was set to
+        radio.borrow_mut().set_reg(0x01, 0x10);
+        assert_eq!(rfm.supervise().await.unwrap(), Some(RecoveryCause::StuckMode));
+        assert_eq!(radio.borrow().resets(), resets + 2);
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+
+        // Waits on a hanging radio time out, until the radio is reset
+        radio.borrow_mut().hang();
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[1]).unwrap();
+        for _ in 0..2 {
+            assert!(matches!(
+                rfm.send(&packet).await,
+                Err(Error::Timeout(WaitState::ModeReady))
+            ));
+        }
+        assert!(matches!(
+            rfm.send(&packet).await,
+            Err(Error::Recovered(RecoveryCause::Timeouts))
+        ));
+        assert_eq!(radio.borrow().resets(), resets + 3);
+        assert_eq!(rfm.recoveries(), 3);
+        assert!(rfm.is_configured(&config).await.unwrap());
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(radio.borrow_mut().pop_tx().unwrap().len(), 6);
+    });
+}
+
+#[test]
+fn cancelled_recv_is_restored() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let rfm = rfm(&radio).with_dio1(SimDio::dio1(&radio));
+        let mut rfm = config::my_defaults(rfm, 42, 868_000_000, None).await.unwrap();
+
+        // Drop the receive while a frame arrives
+        let mut frame = [7; 201];
+        frame[0] = 200;
+        assert!(radio.borrow_mut().push_rx(&frame, -50));
+        {
+            let receive = rfm.recv();
+            pin_mut!(receive);
+            for _ in 0..10 {
+                assert!(receive.as_mut().now_or_never().is_none());
+            }
+        }
+        assert_eq!(radio.borrow().mode(), SimMode::Rx);
+        assert!(radio.borrow().fifo_len() > 0);
+
+        // The next call puts the radio back into standby with an empty fifo first
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[1, 2]).unwrap();
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(&radio.borrow_mut().pop_tx().unwrap()[..], &[6, 2, 1, 0x40, 0, 1, 2]);
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+        assert_eq!(radio.borrow().fifo_len(), 0);
+
+        assert!(radio.borrow_mut().push_rx(&[6, 2, 1, 0x40, 0, 3, 4], -70));
+        assert_eq!(&rfm.recv().await.unwrap().data[..], &[3, 4]);
+
+        // A dropped listen receive leaves listen mode with the next call or with abort
+        {
+            let receive = rfm.listen_recv();
+            pin_mut!(receive);
+            assert!(receive.as_mut().now_or_never().is_none());
+        }
+        assert_eq!(radio.borrow().mode(), SimMode::Listen);
+        rfm.abort().await.unwrap();
+        assert_eq!(radio.borrow().mode(), SimMode::Standby);
+        assert!(radio.borrow_mut().push_rx(&[6, 2, 1, 0x40, 0, 5, 6], -70));
+        assert_eq!(&rfm.listen_recv().await.unwrap().data[..], &[5, 6]);
+    });
+}
+
+#[test]
+fn output_power_per_variant() {
+    let radio = RefCell::new(SimRadio::new());
+    block_on(async {
+        let packet = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[1]).unwrap();
+
+        // RFM69W uses PA0 only
+        let mut rfm = config::my_defaults(rfm(&radio), 42, 868_000_000, None).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x11), 0x9f);
+        assert_eq!(radio.borrow().reg(0x13), 0x1a);
+        rfm.tx_power(-18).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x11), 0x80);
+        assert!(matches!(rfm.tx_power(14).await, Err(Error::OutputPower)));
+        assert!(matches!(
+            rfm.output_power(OutputPower::Pa1(10)).await,
+            Err(Error::OutputPower)
+        ));
+        assert_eq!(radio.borrow().reg(0x11), 0x80);
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(radio.borrow().tx_pa(), [0x80, 0x55, 0x70]);
+
+        // RFM69HW uses PA1, PA1 and PA2, and the high power settings above +17 dBm
+        let rfm = rfm.with_variant(Variant::Rfm69Hw);
+        let mut rfm = config::my_defaults(rfm, 42, 868_000_000, None).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x11), 0x5f);
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(radio.borrow().tx_pa(), [0x5f, 0x55, 0x70]);
+
+        rfm.tx_power(17).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x11), 0x7f);
+        assert_eq!(radio.borrow().reg(0x13), 0x1a);
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(radio.borrow().tx_pa(), [0x7f, 0x55, 0x70]);
+
+        rfm.tx_power(20).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x11), 0x7f);
+        assert_eq!(radio.borrow().reg(0x13), 0x0f);
+        rfm.send(&packet).await.unwrap();
+        assert_eq!(radio.borrow().tx_pa(), [0x7f, 0x5d, 0x7c]);
+        // The high power settings are only used during tx
+        assert_eq!(radio.borrow().reg(0x5a), 0x55);
+        assert_eq!(radio.borrow().reg(0x5c), 0x70);
+
+        // A rejected packet does not touch the radio
+        rfm.aes(Some(&[1; 16])).await.unwrap();
+        let mode = radio.borrow().reg(0x01);
+        let long = Packet::new(Address::Unicast(1), Address::Unicast(2), Flags::new(), &[1; 100]).unwrap();
+        assert!(matches!(rfm.send(&long).await, Err(Error::PacketSize)));
+        assert_eq!(radio.borrow().reg(0x01), mode);
+        assert_eq!(radio.borrow().reg(0x5a), 0x55);
+        assert_eq!(radio.borrow().reg(0x5c), 0x70);
+
+        assert!(matches!(rfm.tx_power(21).await, Err(Error::OutputPower)));
+        assert!(matches!(
+            rfm.output_power(OutputPower::Pa0(0)).await,
+            Err(Error::OutputPower)
+        ));
+        rfm.tx_power(13).await.unwrap();
+        assert_eq!(radio.borrow().reg(0x13), 0x1a);
+    });
+}